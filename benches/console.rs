@@ -0,0 +1,133 @@
+mod common;
+
+use aoc2020::console::{Instruction, Opcode, Program};
+use common::XorShift;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+// straight-line acc/nop program that jumps back to the start, so a run visits every
+// instruction once before the loop is detected
+fn looping_program(len: usize) -> Program {
+    let mut source = (0..len - 1)
+        .map(|i| {
+            if i % 3 == 0 {
+                String::from("nop +0")
+            } else {
+                format!("acc {:+}", (i % 7) as i32 - 3)
+            }
+        })
+        .collect::<Vec<String>>();
+    source.push(format!("jmp -{}", len - 1));
+    Program::parse(&source.join("\n")).unwrap()
+}
+
+// pseudo-random acc/nop/short forward jmp program closing with a jmp back to the start. a run
+// covers most of it before looping, unlike a uniformly random program which loops in a few steps
+fn random_program(len: usize, seed: u64) -> Program {
+    let mut rng = XorShift::new(seed);
+    let mut source = (0..len - 1)
+        .map(|_| match rng.next_u64() % 10 {
+            0..=2 => String::from("nop +0"),
+            3..=8 => format!("acc {:+}", (rng.next_u64() % 201) as i32 - 100),
+            _ => format!("jmp +{}", rng.next_u64() % 3 + 1),
+        })
+        .collect::<Vec<String>>();
+    source.push(format!("jmp -{}", len - 1));
+    Program::parse(&source.join("\n")).unwrap()
+}
+
+#[derive(Clone)]
+struct ReferenceState {
+    accumulator: i32,
+    current_instruction: usize,
+    visited_instructions: Vec<usize>,
+    steps: usize,
+}
+
+// how run worked before the bitset: the visited instructions are a sorted Vec searched and
+// inserted into every step, and each step clones the whole state. returns the accumulator
+// wherever it stops
+fn reference_run(instructions: &[Instruction]) -> i32 {
+    let mut state = ReferenceState {
+        accumulator: 0,
+        current_instruction: 0,
+        visited_instructions: Vec::new(),
+        steps: 0,
+    };
+    loop {
+        let pc = state.current_instruction;
+        let instruction = match instructions.get(pc) {
+            Some(instruction) => instruction,
+            None => return state.accumulator,
+        };
+        let mut next = state.clone();
+        match next.visited_instructions.binary_search(&pc) {
+            Ok(_) => return state.accumulator,
+            Err(index) => next.visited_instructions.insert(index, pc),
+        }
+        match instruction.op {
+            Opcode::Acc => match state.accumulator.checked_add(instruction.value) {
+                Some(accumulator) => {
+                    next.accumulator = accumulator;
+                    next.current_instruction += 1;
+                }
+                None => return state.accumulator,
+            },
+            Opcode::Jmp => {
+                let target = pc as i64 + instruction.value as i64;
+                if target < 0 || target > instructions.len() as i64 {
+                    return state.accumulator;
+                }
+                next.current_instruction = target as usize;
+            }
+            _ => next.current_instruction += 1,
+        }
+        next.steps += 1;
+        state = next;
+    }
+}
+
+// the reference is quadratic, which keeps the sizes down and the sample count low
+fn run(c: &mut Criterion) {
+    let mut group = c.benchmark_group("run");
+    group.sample_size(10);
+    for len in [1_000, 10_000, 100_000] {
+        let program = looping_program(len);
+        assert_eq!(
+            reference_run(program.instructions()),
+            program.run().into_state().accumulator
+        );
+        group.throughput(Throughput::Elements(len as u64));
+        group.bench_with_input(BenchmarkId::new("bitset", len), &program, |b, program| {
+            b.iter(|| program.run())
+        });
+        group.bench_with_input(
+            BenchmarkId::new("sorted_vec", len),
+            &program,
+            |b, program| b.iter(|| reference_run(program.instructions())),
+        );
+    }
+    group.finish();
+}
+
+fn compiled(c: &mut Criterion) {
+    let mut group = c.benchmark_group("interpreter_vs_compiled");
+    for len in [10_000, 100_000, 500_000] {
+        let program = random_program(len, len as u64);
+        let compiled = program.compile();
+        group.throughput(Throughput::Elements(len as u64));
+        group.bench_with_input(
+            BenchmarkId::new("interpreter", len),
+            &program,
+            |b, program| b.iter(|| program.run()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("compiled", len),
+            &compiled,
+            |b, compiled| b.iter(|| compiled.run()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, run, compiled);
+criterion_main!(benches);
@@ -0,0 +1,61 @@
+mod common;
+
+use aoc2020::day1::index::PairIndex;
+use aoc2020::day1::{find_match, ksum};
+use common::XorShift;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+// expense-report-like entries, pseudo-random in 1..2000
+fn entries(len: usize, seed: u64) -> Vec<i64> {
+    let mut rng = XorShift::new(seed);
+    (0..len)
+        .map(|_| (rng.next_u64() % 1999) as i64 + 1)
+        .collect()
+}
+
+// every target from 2000 to 2038 as a pair and a triple, a mix of hits and misses
+fn queries() -> Vec<(i64, usize)> {
+    (2000..2040)
+        .step_by(2)
+        .flat_map(|target| vec![(target, 2), (target, 3)])
+        .collect()
+}
+
+fn batch(c: &mut Criterion) {
+    let queries = queries();
+    let mut group = c.benchmark_group("batch_queries");
+    group.sample_size(10);
+    for len in [50, 100] {
+        let entries = entries(len, len as u64);
+        group.bench_with_input(
+            BenchmarkId::new("find_match", len),
+            &entries,
+            |b, entries| {
+                b.iter(|| {
+                    queries
+                        .iter()
+                        .map(|&(target, k)| find_match(entries, target, k))
+                        .collect::<Vec<_>>()
+                })
+            },
+        );
+        group.bench_with_input(BenchmarkId::new("ksum", len), &entries, |b, entries| {
+            b.iter(|| {
+                queries
+                    .iter()
+                    .map(|&(target, k)| ksum::find(entries, target, k))
+                    .collect::<Vec<_>>()
+            })
+        });
+        // building the index is part of every iteration
+        group.bench_with_input(
+            BenchmarkId::new("pair_index", len),
+            &entries,
+            |b, entries| b.iter(|| PairIndex::new(entries).query_all(&queries)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, batch);
+criterion_main!(benches);
@@ -0,0 +1,30 @@
+extern crate aoc2020;
+
+use aoc2020::console::asm;
+use aoc2020::console::debugger::Debugger;
+use std::io;
+
+fn main() {
+    let path = match std::env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: console_debugger <program file>");
+            std::process::exit(2);
+        }
+    };
+    let source = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+        eprintln!("couldn't read {}: {}", path, e);
+        std::process::exit(1);
+    });
+    let program = asm::assemble(&source).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        std::process::exit(1);
+    });
+
+    let stdin = io::stdin();
+    let mut debugger = Debugger::new(&program);
+    if let Err(e) = debugger.repl(stdin.lock(), io::stdout()) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,14 @@
+extern crate aoc2020;
+
+use aoc2020::console::lsp;
+use std::io;
+
+// language server for console assembly, speaking lsp on stdin/stdout
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    if let Err(e) = lsp::serve(stdin.lock(), stdout.lock()) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
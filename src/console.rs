@@ -1,146 +1,743 @@
-#[derive(Debug, Clone)]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+pub mod analysis;
+pub mod asm;
+pub mod cfg;
+pub mod codegen;
+pub mod compiled;
+pub mod debugger;
+pub mod decompile;
+pub mod diff;
+pub mod generate;
+pub mod io;
+pub mod isa;
+pub mod lsp;
+pub mod mutation;
+pub mod network;
+pub mod optimize;
+pub mod snapshot;
+pub mod trace;
+
+use io::{ConsoleIo, NoIo};
+use trace::{NoTrace, TraceStep, Tracer};
+
+// bitset over instruction indices, grows on insert so it works for any program length.
+// serialized as its words, so a restored set is identical down to its capacity in words
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<u64>", into = "Vec<u64>")]
+pub struct VisitedSet {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl VisitedSet {
+    pub fn new() -> Self {
+        VisitedSet::default()
+    }
+
+    pub fn with_capacity(instructions: usize) -> Self {
+        VisitedSet {
+            words: Vec::with_capacity(instructions.div_ceil(64)),
+            len: 0,
+        }
+    }
+
+    pub fn contains(&self, pc: usize) -> bool {
+        self.words
+            .get(pc / 64)
+            .is_some_and(|word| word & (1 << (pc % 64)) != 0)
+    }
+
+    // returns false if pc was already in the set
+    pub fn insert(&mut self, pc: usize) -> bool {
+        if pc / 64 >= self.words.len() {
+            self.words.resize(pc / 64 + 1, 0);
+        }
+        let word = &mut self.words[pc / 64];
+        let bit = 1 << (pc % 64);
+        if *word & bit != 0 {
+            return false;
+        }
+        *word |= bit;
+        self.len += 1;
+        true
+    }
+
+    pub fn remove(&mut self, pc: usize) -> bool {
+        match self.words.get_mut(pc / 64) {
+            Some(word) if *word & (1 << (pc % 64)) != 0 => {
+                *word &= !(1 << (pc % 64));
+                self.len -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.words.iter_mut().for_each(|word| *word = 0);
+        self.len = 0;
+    }
+
+    // visited instructions in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, &word)| {
+            (0..64)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| i * 64 + bit)
+        })
+    }
+}
+
+impl From<Vec<u64>> for VisitedSet {
+    fn from(words: Vec<u64>) -> Self {
+        let len = words.iter().map(|word| word.count_ones() as usize).sum();
+        VisitedSet { words, len }
+    }
+}
+
+impl From<VisitedSet> for Vec<u64> {
+    fn from(set: VisitedSet) -> Self {
+        set.words
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProgramState {
     pub accumulator: i32,
     pub current_instruction: usize,
-    pub visited_instructions: Vec<usize>,
+    pub visited_instructions: VisitedSet,
+    pub steps: usize,
 }
 
 impl ProgramState {
-    fn new() -> Self {
+    pub fn new() -> Self {
         ProgramState {
             accumulator: 0,
             current_instruction: 0,
-            visited_instructions: Vec::<usize>::new(),
+            visited_instructions: VisitedSet::new(),
+            steps: 0,
+        }
+    }
+}
+
+impl Default for ProgramState {
+    fn default() -> Self {
+        ProgramState::new()
+    }
+}
+
+// why a run stopped, along with the state it stopped in. generic so machines with other
+// state (see isa) can report the same reasons
+#[derive(Debug, Clone)]
+pub enum Termination<S = ProgramState> {
+    // pc reached one past the last instruction
+    Halted(S),
+    // pc was about to execute an instruction for the second time (or, for an isa machine
+    // using LoopDetection::RepeatedState, about to repeat a whole state it was in before)
+    InfiniteLoop {
+        pc: usize,
+        revisited_at_step: usize,
+        state: S,
+    },
+    // the instruction at pc would move execution to target, which is outside the program
+    OutOfBounds {
+        pc: usize,
+        target: i64,
+        state: S,
+    },
+    StepLimitExceeded(S),
+    // the instruction at pc can't be executed, e.g. the accumulator would overflow
+    InvalidInstruction {
+        pc: usize,
+        state: S,
+    },
+    // the `in` at pc has no input yet. running again from state retries it
+    AwaitingInput {
+        pc: usize,
+        state: S,
+    },
+}
+
+impl<S> Termination<S> {
+    pub fn state(&self) -> &S {
+        match self {
+            Termination::Halted(state) => state,
+            Termination::InfiniteLoop { state, .. } => state,
+            Termination::OutOfBounds { state, .. } => state,
+            Termination::StepLimitExceeded(state) => state,
+            Termination::InvalidInstruction { state, .. } => state,
+            Termination::AwaitingInput { state, .. } => state,
+        }
+    }
+
+    pub fn into_state(self) -> S {
+        match self {
+            Termination::Halted(state) => state,
+            Termination::InfiniteLoop { state, .. } => state,
+            Termination::OutOfBounds { state, .. } => state,
+            Termination::StepLimitExceeded(state) => state,
+            Termination::InvalidInstruction { state, .. } => state,
+            Termination::AwaitingInput { state, .. } => state,
+        }
+    }
+
+    pub fn is_halted(&self) -> bool {
+        matches!(self, Termination::Halted(_))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Opcode {
+    Nop,
+    Acc,
+    Jmp,
+    // acc = value read from port +N, blocking until one is available
+    In,
+    // write acc to port +N
+    Out,
+}
+
+impl Opcode {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Opcode::Nop => "nop",
+            Opcode::Acc => "acc",
+            Opcode::Jmp => "jmp",
+            Opcode::In => "in",
+            Opcode::Out => "out",
         }
     }
 }
 
-#[derive(Clone)]
+impl FromStr for Opcode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nop" => Ok(Opcode::Nop),
+            "acc" => Ok(Opcode::Acc),
+            "jmp" => Ok(Opcode::Jmp),
+            "in" => Ok(Opcode::In),
+            "out" => Ok(Opcode::Out),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.mnemonic())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    MissingOp,
+    UnknownOp,
+    MissingArgument,
+    InvalidArgument,
+    TrailingInput,
+    UndefinedSymbol,
+    DuplicateSymbol,
+    InvalidSymbol,
+}
+
+// line and column are 1-based and point into the original (untrimmed) input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let what = match self.kind {
+            ParseErrorKind::MissingOp => "missing op",
+            ParseErrorKind::UnknownOp => "unknown op",
+            ParseErrorKind::MissingArgument => "missing argument",
+            ParseErrorKind::InvalidArgument => "invalid argument",
+            ParseErrorKind::TrailingInput => "unexpected trailing input",
+            ParseErrorKind::UndefinedSymbol => "undefined label or constant",
+            ParseErrorKind::DuplicateSymbol => "label or constant already defined",
+            ParseErrorKind::InvalidSymbol => "invalid label or constant name",
+        };
+        write!(
+            f,
+            "{} at line {}, column {}: {:?}",
+            what, self.line, self.column, self.text
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// split on whitespace, remembering the 1-based column each token starts at
+fn tokens(line: &str) -> impl Iterator<Item = (usize, &str)> {
+    line.split_whitespace().map(move |token| {
+        let offset = token.as_ptr() as usize - line.as_ptr() as usize;
+        (line[..offset].chars().count() + 1, token)
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Instruction {
-    op: String,
-    value: i32,
+    pub op: Opcode,
+    pub value: i32,
+}
+
+impl Instruction {
+    fn parse(line: &str, line_number: usize) -> Result<Self, ParseError> {
+        let error = |kind, column, text: &str| ParseError {
+            kind,
+            line: line_number,
+            column,
+            text: String::from(text),
+        };
+
+        let mut tokens = tokens(line);
+
+        let (op_column, op_text) = match tokens.next() {
+            Some(token) => token,
+            None => return Err(error(ParseErrorKind::MissingOp, 1, line)),
+        };
+        let op = op_text
+            .parse::<Opcode>()
+            .map_err(|_| error(ParseErrorKind::UnknownOp, op_column, op_text))?;
+
+        let (value_column, value_text) = match tokens.next() {
+            Some(token) => token,
+            None => {
+                let end = line.trim_end().chars().count() + 1;
+                return Err(error(ParseErrorKind::MissingArgument, end, line.trim()));
+            }
+        };
+        let value = value_text
+            .parse::<i32>()
+            .map_err(|_| error(ParseErrorKind::InvalidArgument, value_column, value_text))?;
+
+        if let Some((column, text)) = tokens.next() {
+            return Err(error(ParseErrorKind::TrailingInput, column, text));
+        }
+
+        Ok(Instruction { op, value })
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:+}", self.op, self.value)
+    }
 }
 
-#[derive(Clone)]
+// serialized as its list of instructions, see snapshot for how those look
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Program {
     instructions: Vec<Instruction>,
 }
 
 impl Program {
+    pub fn new(instructions: Vec<Instruction>) -> Self {
+        Program { instructions }
+    }
+
     pub fn as_ref(&self) -> &Self {
         self
     }
 
-    pub fn parse(input: &str) -> Self {
-        Program {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        Ok(Program {
             instructions: input
                 .lines()
-                .map(|l| {
-                    let mut op_pair = l.trim().split(" ");
-                    if let (Some(op), Some(val)) = (op_pair.next(), op_pair.next()) {
-                        Instruction {
-                            op: String::from(op),
-                            value: val.parse::<i32>().expect("Invalid modifer to parse"),
-                        }
-                    } else {
-                        panic!("encountered badly formed row")
-                    }
-                })
-                .collect::<Vec<Instruction>>(),
-        }
+                .enumerate()
+                .map(|(i, l)| Instruction::parse(l, i + 1))
+                .collect::<Result<Vec<Instruction>, ParseError>>()?,
+        })
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
     }
 
     pub fn twiddle(&mut self, program_state: &ProgramState) {
         let instruction = &mut self.instructions[program_state.current_instruction];
-        match instruction.op.as_str() {
-            "nop" => instruction.op = String::from("jmp"),
-            "jmp" => instruction.op = String::from("nop"),
-            "acc" => instruction.op = String::from("acc"),
-            _ => panic!("Invalid op to twiddle"),
+        match instruction.op {
+            Opcode::Nop => instruction.op = Opcode::Jmp,
+            Opcode::Jmp => instruction.op = Opcode::Nop,
+            Opcode::Acc | Opcode::In | Opcode::Out => {}
         };
     }
 
-    fn step(&self, program_state: &ProgramState) -> Result<ProgramState, ProgramState> {
-        let mut next_program_state = program_state.clone();
+    // advances the state by one instruction in place, handing it back inside the termination
+    // (unchanged) if the instruction can't be executed
+    fn step(
+        &self,
+        mut program_state: ProgramState,
+        io: &mut impl ConsoleIo,
+    ) -> Result<ProgramState, Termination> {
+        let pc = program_state.current_instruction;
 
-        // terminate on infinite loop
-        match next_program_state
-            .visited_instructions
-            .binary_search(&program_state.current_instruction)
-        {
-            Ok(_) => return Err(next_program_state),
-            Err(ind) => {
-                next_program_state
-                    .visited_instructions
-                    .insert(ind, program_state.current_instruction);
-            }
+        // terminate on reaching final instruction (1 out of program bounds)
+        if pc == self.instructions.len() {
+            return Err(Termination::Halted(program_state));
         }
 
-        let instruction = self
-            .instructions
-            .get(program_state.current_instruction)
-            .expect("ran out of bounds of program memory (check a jmp or similar)");
+        let instruction = match self.instructions.get(pc) {
+            Some(instruction) => instruction,
+            None => {
+                return Err(Termination::OutOfBounds {
+                    pc,
+                    target: pc as i64,
+                    state: program_state,
+                })
+            }
+        };
+
+        // terminate on infinite loop. an `in` can always be revisited, what follows depends on the input
+        if instruction.op != Opcode::In && program_state.visited_instructions.contains(pc) {
+            return Err(Termination::InfiniteLoop {
+                pc,
+                revisited_at_step: program_state.steps,
+                state: program_state,
+            });
+        }
 
-        match instruction.op.as_str() {
-            "nop" => {
-                next_program_state.current_instruction += 1;
+        match instruction.op {
+            Opcode::Nop => {
+                program_state.current_instruction += 1;
             }
-            "acc" => {
-                next_program_state.accumulator += instruction.value;
-                next_program_state.current_instruction += 1;
+            Opcode::Acc => {
+                program_state.accumulator =
+                    match program_state.accumulator.checked_add(instruction.value) {
+                        Some(accumulator) => accumulator,
+                        None => {
+                            return Err(Termination::InvalidInstruction {
+                                pc,
+                                state: program_state,
+                            })
+                        }
+                    };
+                program_state.current_instruction += 1;
             }
-            "jmp" => {
-                if instruction.value > 0 {
-                    next_program_state.current_instruction += instruction.value as usize;
-                } else {
-                    next_program_state.current_instruction -= (-instruction.value) as usize;
+            Opcode::Jmp => {
+                let target = pc as i64 + instruction.value as i64;
+                if target < 0 || target > self.instructions.len() as i64 {
+                    return Err(Termination::OutOfBounds {
+                        pc,
+                        target,
+                        state: program_state,
+                    });
                 }
+                program_state.current_instruction = target as usize;
+            }
+            Opcode::In => {
+                program_state.accumulator = match io.input(instruction.value) {
+                    Some(value) => value,
+                    None => {
+                        return Err(Termination::AwaitingInput {
+                            pc,
+                            state: program_state,
+                        })
+                    }
+                };
+                // new input means revisiting an instruction no longer implies a loop
+                program_state.visited_instructions.clear();
+                program_state.current_instruction += 1;
+            }
+            Opcode::Out => {
+                io.output(instruction.value, program_state.accumulator);
+                program_state.current_instruction += 1;
             }
-            _ => {}
         }
+        program_state.visited_instructions.insert(pc);
+        program_state.steps += 1;
 
-        Ok(next_program_state)
+        Ok(program_state)
     }
 
+    // max_steps bounds the number of instructions executed by this call, not the total in the state
     pub fn run_from(
         &self,
         starting_program_state: &ProgramState,
-    ) -> Result<ProgramState, ProgramState> {
+        max_steps: Option<usize>,
+    ) -> Termination {
+        self.run_with(starting_program_state, max_steps, &mut NoIo, &mut NoTrace)
+    }
+
+    // run_from, reporting every executed instruction to the tracer
+    pub fn run_traced(
+        &self,
+        starting_program_state: &ProgramState,
+        max_steps: Option<usize>,
+        tracer: &mut impl Tracer,
+    ) -> Termination {
+        self.run_with(starting_program_state, max_steps, &mut NoIo, tracer)
+    }
+
+    // run_from with in/out connected to io. returns AwaitingInput when io has nothing to read,
+    // and calling again with that state picks up at the same `in`
+    pub fn run_io(
+        &self,
+        starting_program_state: &ProgramState,
+        max_steps: Option<usize>,
+        io: &mut impl ConsoleIo,
+    ) -> Termination {
+        self.run_with(starting_program_state, max_steps, io, &mut NoTrace)
+    }
+
+    pub fn run_with(
+        &self,
+        starting_program_state: &ProgramState,
+        max_steps: Option<usize>,
+        io: &mut impl ConsoleIo,
+        tracer: &mut impl Tracer,
+    ) -> Termination {
         let mut program_state = starting_program_state.clone();
+        let mut gas = max_steps.unwrap_or(usize::MAX);
         loop {
-            // terminate on reaching final instruction (1 out of program bounds)
-            if program_state.current_instruction == self.instructions.len() {
-                return Ok(program_state);
-            }
-            match self.step(&program_state) {
-                Ok(state) => program_state = state,
-                error => return error,
+            if gas == 0 && program_state.current_instruction != self.instructions.len() {
+                return Termination::StepLimitExceeded(program_state);
             }
+            let pc = program_state.current_instruction;
+            let accumulator_before = program_state.accumulator;
+            program_state = match self.step(program_state, io) {
+                Ok(state) => state,
+                Err(termination) => return termination,
+            };
+            let instruction = self.instructions[pc];
+            tracer.record(&TraceStep {
+                step: program_state.steps - 1,
+                pc,
+                op: instruction.op,
+                value: instruction.value,
+                accumulator_before,
+                accumulator_after: program_state.accumulator,
+            });
+            gas = gas.saturating_sub(1);
         }
     }
 
-    pub fn run(&self) -> Result<ProgramState, ProgramState> {
+    pub fn run(&self) -> Termination {
         let program_state = ProgramState::new();
-        self.run_from(&program_state)
+        self.run_from(&program_state, None)
     }
 
-    pub fn fix_and_run(&self) -> Result<ProgramState, ProgramState> {
-        let mut program_state = ProgramState::new();
-        let mut modified_program = self.clone();
-        // change program at each line (doesn't always change), see if it completes. if not, go to next instruction
-        loop {
-            modified_program.twiddle(&program_state);
-            // run from this point to see if it terminates or infinitely loops
-            match modified_program.run_from(&program_state) {
-                Ok(state) => return Ok(state),
-                Err(_) => {
-                    // try again, ie return to normal
-                    modified_program.twiddle(&program_state);
-                }
+    // runs the program with the first single nop/jmp swap along its path that makes it halt,
+    // or returns how the unmodified program stopped if it already halts or no swap works
+    pub fn fix_and_run(&self) -> Termination {
+        match self.repairs().first() {
+            Some(repair) => {
+                let mut modified_program = self.clone();
+                repair.apply(&mut modified_program);
+                modified_program.run()
+            }
+            None => self.run(),
+        }
+    }
+}
+
+// canonical text, one `op +N` per line, which Program::parse reads back unchanged
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for instruction in &self.instructions {
+            writeln!(f, "{}", instruction)?;
+        }
+        Ok(())
+    }
+}
+
+// fixtures shared by the tests of the console submodules
+#[cfg(test)]
+pub(crate) mod test_support {
+    use super::{Instruction, Opcode};
+    use proptest::prelude::*;
+
+    // the day8 example, which loops and is fixed by swapping the jmp at 7
+    pub(crate) const SAMPLE: &str = "nop +0
+                                     acc +1
+                                     jmp +4
+                                     acc +3
+                                     jmp -3
+                                     acc -99
+                                     acc +1
+                                     jmp -4
+                                     acc +6";
+
+    fn instruction_from(
+        ops: impl Strategy<Value = Opcode>,
+        values: impl Strategy<Value = i32>,
+    ) -> impl Strategy<Value = Instruction> {
+        (ops, values).prop_map(|(op, value)| Instruction { op, value })
+    }
+
+    pub(crate) fn instruction_with(
+        values: impl Strategy<Value = i32>,
+    ) -> impl Strategy<Value = Instruction> {
+        instruction_from(
+            prop_oneof![Just(Opcode::Nop), Just(Opcode::Acc), Just(Opcode::Jmp)],
+            values,
+        )
+    }
+
+    // small enough to jump around inside a short program, or at the extremes so accs overflow
+    pub(crate) fn values() -> impl Strategy<Value = i32> {
+        prop_oneof![-6i32..7, Just(i32::MAX), Just(i32::MIN)]
+    }
+
+    // nop, acc and jmp with those values
+    pub(crate) fn instruction() -> impl Strategy<Value = Instruction> {
+        instruction_with(values())
+    }
+
+    // the same with the occasional in and out
+    pub(crate) fn io_instruction() -> impl Strategy<Value = Instruction> {
+        instruction_from(
+            prop_oneof![
+                4 => Just(Opcode::Nop),
+                4 => Just(Opcode::Acc),
+                4 => Just(Opcode::Jmp),
+                1 => Just(Opcode::In),
+                1 => Just(Opcode::Out),
+            ],
+            values(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ops() {
+        let program = Program::parse("nop +0\n  acc -7\njmp +3").unwrap();
+        assert_eq!(
+            program.instructions(),
+            &[
+                Instruction {
+                    op: Opcode::Nop,
+                    value: 0
+                },
+                Instruction {
+                    op: Opcode::Acc,
+                    value: -7
+                },
+                Instruction {
+                    op: Opcode::Jmp,
+                    value: 3
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = Program::parse("nop +0\n  mul +2").err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::UnknownOp);
+        assert_eq!((error.line, error.column), (2, 3));
+        assert_eq!(error.text, "mul");
+
+        let error = Program::parse("acc +x").err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::InvalidArgument);
+        assert_eq!((error.line, error.column), (1, 5));
+        assert_eq!(error.text, "+x");
+
+        let error = Program::parse("jmp").err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::MissingArgument);
+
+        let error = Program::parse("jmp +1 +2").err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::TrailingInput);
+        assert_eq!(error.column, 8);
+
+        let error = Program::parse("nop +0\n\nnop +0").err().unwrap();
+        assert_eq!((error.kind, error.line), (ParseErrorKind::MissingOp, 2));
+    }
+
+    #[test]
+    fn terminations() {
+        let program = Program::parse("acc +2\njmp +1\nacc +1").unwrap();
+        match program.run() {
+            Termination::Halted(state) => assert_eq!((state.accumulator, state.steps), (3, 3)),
+            other => panic!("unexpected termination {:?}", other),
+        }
+
+        let program = Program::parse("nop +0\nacc +1\njmp -1").unwrap();
+        match program.run() {
+            Termination::InfiniteLoop {
+                pc,
+                revisited_at_step,
+                state,
+            } => assert_eq!((pc, revisited_at_step, state.accumulator), (1, 3, 1)),
+            other => panic!("unexpected termination {:?}", other),
+        }
+
+        let program = Program::parse("acc +1\njmp -2").unwrap();
+        match program.run() {
+            Termination::OutOfBounds { pc, target, state } => {
+                assert_eq!((pc, target, state.accumulator), (1, -1, 1))
+            }
+            other => panic!("unexpected termination {:?}", other),
+        }
+
+        let program = Program::parse("jmp +3\nnop +0").unwrap();
+        match program.run() {
+            Termination::OutOfBounds { pc, target, .. } => assert_eq!((pc, target), (0, 3)),
+            other => panic!("unexpected termination {:?}", other),
+        }
+
+        let program = Program::parse("acc +2147483647\nacc +1").unwrap();
+        match program.run() {
+            Termination::InvalidInstruction { pc, state } => {
+                assert_eq!((pc, state.accumulator), (1, i32::MAX))
+            }
+            other => panic!("unexpected termination {:?}", other),
+        }
+    }
+
+    #[test]
+    fn visited_set() {
+        let mut visited = VisitedSet::new();
+        assert!(visited.insert(3));
+        assert!(visited.insert(130));
+        assert!(!visited.insert(3));
+        assert!(visited.contains(130) && !visited.contains(4) && !visited.contains(1000));
+        assert_eq!(visited.iter().collect::<Vec<usize>>(), vec![3, 130]);
+        assert!(visited.remove(3));
+        assert_eq!(visited.len(), 1);
+        visited.clear();
+        assert!(visited.is_empty());
+    }
+
+    #[test]
+    fn step_limit() {
+        let program = Program::parse("acc +1\nacc +1\nacc +1").unwrap();
+        match program.run_from(&ProgramState::new(), Some(2)) {
+            Termination::StepLimitExceeded(state) => {
+                assert_eq!((state.current_instruction, state.accumulator), (2, 2))
             }
-            program_state = modified_program
-                .step(&program_state)
-                .expect("infinitely looped, not fixed");
+            other => panic!("unexpected termination {:?}", other),
         }
+        assert!(program.run_from(&ProgramState::new(), Some(3)).is_halted());
     }
 }
@@ -0,0 +1,274 @@
+use super::cfg::ControlFlowGraph;
+use super::{Opcode, Program};
+use std::fmt;
+
+// what Program::run will do, worked out from the control flow graph instead of the vm. with one
+// successor per instruction the run follows the graph's execution path, which ends at the exit,
+// out of bounds or on one of its cycles. the accumulator is exact, summed along the path, and only
+// changes the verdict where an acc overflows or an `in` waits for input before the path ends
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Halts {
+        accumulator: i32,
+    },
+    // pc is the first instruction to repeat, body the loop from there in execution order
+    Loops {
+        pc: usize,
+        body: Vec<usize>,
+        accumulator: i32,
+        accumulator_per_pass: i64,
+    },
+    OutOfBounds {
+        pc: usize,
+        target: i64,
+    },
+    Overflow {
+        pc: usize,
+        accumulator: i32,
+    },
+    // run has no input to give it
+    AwaitsInput {
+        pc: usize,
+        accumulator: i32,
+    },
+}
+
+impl Verdict {
+    pub fn terminates(&self) -> bool {
+        matches!(self, Verdict::Halts { .. })
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Verdict::Halts { accumulator } => write!(f, "halts with acc {}", accumulator),
+            Verdict::Loops {
+                pc,
+                body,
+                accumulator,
+                accumulator_per_pass,
+            } => write!(
+                f,
+                "loops forever: {} repeats with acc {}, the {} instruction loop changes acc by {:+} per pass",
+                pc,
+                accumulator,
+                body.len(),
+                accumulator_per_pass
+            ),
+            Verdict::OutOfBounds { pc, target } => {
+                write!(f, "jmp at {} goes out of bounds to {}", pc, target)
+            }
+            Verdict::Overflow { pc, accumulator } => {
+                write!(f, "acc at {} overflows from {}", pc, accumulator)
+            }
+            Verdict::AwaitsInput { pc, accumulator } => {
+                write!(f, "waits for input at {} with acc {}", pc, accumulator)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Analysis {
+    pub verdict: Verdict,
+    // instructions executed from pc 0, in order
+    pub path: Vec<usize>,
+    // every cycle in the program, reachable or not, each starting at its lowest pc
+    pub loops: Vec<Vec<usize>>,
+    // instructions no run from pc 0 gets to, whatever the accumulator does
+    pub unreachable: Vec<usize>,
+}
+
+impl Analysis {
+    pub fn new(program: &Program) -> Self {
+        let cfg = ControlFlowGraph::new(program);
+        let loops = cfg.cycles();
+        let (verdict, path) = verdict(program, &cfg, &loops);
+
+        let mut reachable = vec![false; program.len()];
+        for pc in cfg.execution_path() {
+            reachable[pc] = true;
+        }
+        let unreachable = (0..program.len()).filter(|&pc| !reachable[pc]).collect();
+
+        Analysis {
+            verdict,
+            path,
+            loops,
+            unreachable,
+        }
+    }
+}
+
+fn verdict(
+    program: &Program,
+    cfg: &ControlFlowGraph,
+    loops: &[Vec<usize>],
+) -> (Verdict, Vec<usize>) {
+    let instructions = program.instructions();
+    let mut path = cfg.execution_path();
+
+    // the accumulator only matters where it can stop the run early, an `in` or an acc that
+    // overflows. everything before those is summed along the path
+    let mut accumulator = 0i32;
+    for (i, &pc) in path.iter().enumerate() {
+        let instruction = instructions[pc];
+        let stopped = match instruction.op {
+            Opcode::In => Some(Verdict::AwaitsInput { pc, accumulator }),
+            Opcode::Acc => match accumulator.checked_add(instruction.value) {
+                Some(sum) => {
+                    accumulator = sum;
+                    None
+                }
+                None => Some(Verdict::Overflow { pc, accumulator }),
+            },
+            Opcode::Nop | Opcode::Jmp | Opcode::Out => None,
+        };
+        if let Some(verdict) = stopped {
+            path.truncate(i);
+            return (verdict, path);
+        }
+    }
+
+    // otherwise the path ends where its last instruction leads: the exit, out of bounds, or back
+    // onto the path, which closes one of the cycles
+    let last = match path.last() {
+        Some(&last) => last,
+        None => return (Verdict::Halts { accumulator }, path),
+    };
+    let verdict = match cfg.successor(last) {
+        Some(next) if next == instructions.len() => Verdict::Halts { accumulator },
+        Some(next) => {
+            let mut body = loops
+                .iter()
+                .find(|cycle| cycle.contains(&next))
+                .cloned()
+                .unwrap_or_default();
+            let start = body.iter().position(|&pc| pc == next).unwrap_or(0);
+            body.rotate_left(start);
+            let accumulator_per_pass = body
+                .iter()
+                .filter(|&&pc| instructions[pc].op == Opcode::Acc)
+                .map(|&pc| instructions[pc].value as i64)
+                .sum();
+            Verdict::Loops {
+                pc: next,
+                body,
+                accumulator,
+                accumulator_per_pass,
+            }
+        }
+        None => {
+            path.pop();
+            Verdict::OutOfBounds {
+                pc: last,
+                target: last as i64 + instructions[last].value as i64,
+            }
+        }
+    };
+    (verdict, path)
+}
+
+impl Program {
+    pub fn analyze(&self) -> Analysis {
+        Analysis::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::generate::Generator;
+    use super::super::test_support::{io_instruction, SAMPLE};
+    use super::super::Termination;
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn sample() {
+        let analysis = Program::parse(SAMPLE).unwrap().analyze();
+        assert_eq!(
+            analysis.verdict,
+            Verdict::Loops {
+                pc: 1,
+                body: vec![1, 2, 6, 7, 3, 4],
+                accumulator: 5,
+                accumulator_per_pass: 5
+            }
+        );
+        assert_eq!(analysis.path, vec![0, 1, 2, 6, 7, 3, 4]);
+        assert_eq!(analysis.loops, vec![vec![1, 2, 6, 7, 3, 4]]);
+        assert_eq!(analysis.unreachable, vec![5, 8]);
+
+        let fixed = Program::parse(&SAMPLE.replace("jmp -4", "nop -4")).unwrap();
+        let analysis = fixed.analyze();
+        assert_eq!(analysis.verdict, Verdict::Halts { accumulator: 8 });
+        assert_eq!(analysis.verdict.to_string(), "halts with acc 8");
+        assert!(analysis.loops.is_empty());
+        assert_eq!(analysis.unreachable, vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn dead_loops() {
+        // a loop nothing reaches, and one made unreachable by an out of bounds jmp
+        let analysis = Program::parse("jmp +3\nacc +1\njmp -1\nacc +2\njmp -9\njmp +0")
+            .unwrap()
+            .analyze();
+        assert_eq!(analysis.verdict, Verdict::OutOfBounds { pc: 4, target: -5 });
+        assert_eq!(analysis.loops, vec![vec![1, 2], vec![5]]);
+        assert_eq!(analysis.unreachable, vec![1, 2, 5]);
+    }
+
+    // the analysis has to agree with the vm on everything it reports
+    fn agrees(program: &Program) -> bool {
+        let analysis = program.analyze();
+        match (&analysis.verdict, program.run()) {
+            (Verdict::Halts { accumulator }, Termination::Halted(state)) => {
+                *accumulator == state.accumulator
+            }
+            (
+                Verdict::Loops {
+                    pc, accumulator, ..
+                },
+                Termination::InfiniteLoop {
+                    pc: looped_at,
+                    state,
+                    ..
+                },
+            ) => *pc == looped_at && *accumulator == state.accumulator,
+            (
+                Verdict::OutOfBounds { pc, target },
+                Termination::OutOfBounds {
+                    pc: p, target: t, ..
+                },
+            ) => *pc == p && *target == t,
+            (
+                Verdict::Overflow { pc, accumulator },
+                Termination::InvalidInstruction { pc: p, state },
+            ) => *pc == p && *accumulator == state.accumulator,
+            (
+                Verdict::AwaitsInput { pc, accumulator },
+                Termination::AwaitingInput { pc: p, state },
+            ) => *pc == p && *accumulator == state.accumulator,
+            _ => false,
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn matches_run(instructions in prop::collection::vec(io_instruction(), 0..60)) {
+            prop_assert!(agrees(&Program::new(instructions)));
+        }
+
+        #[test]
+        fn generated(seed: u64, len in 2usize..200, accumulator: i32) {
+            let mut generator = Generator::new(seed);
+            let program = generator.terminating(len, accumulator);
+            prop_assert_eq!(program.analyze().verdict, Verdict::Halts { accumulator });
+            let (program, _) = generator.looping(len, accumulator);
+            prop_assert!(!program.analyze().verdict.terminates());
+            prop_assert!(agrees(&program));
+        }
+    }
+}
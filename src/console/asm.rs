@@ -0,0 +1,425 @@
+use super::{tokens, Instruction, Opcode, ParseError, ParseErrorKind, Program};
+use std::collections::HashMap;
+
+// assembly source on top of the plain `op +N` format:
+//
+//     const step = 3    # named constants
+//     start:            # labels, on their own line or in front of an instruction
+//         acc step
+//     loop: acc -1
+//         jmp loop      # label arguments become the relative offset to the label
+//
+// `#` starts a comment and blank lines are ignored. a label after the last instruction points
+// at the exit, so `jmp end` halts
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Symbol {
+    // pc the label is in front of
+    Label(usize),
+    Constant(i32),
+}
+
+// where the parts of an assembled program came from, for editor tooling. lines and columns are
+// 1-based like ParseError's
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceInstruction {
+    pub line: usize,
+    pub column: usize,
+    pub argument: String,
+    pub argument_column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolDefinition {
+    pub name: String,
+    pub symbol: Symbol,
+    pub line: usize,
+    pub column: usize,
+}
+
+// instructions are indexed by pc
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    pub instructions: Vec<SourceInstruction>,
+    pub symbols: Vec<SymbolDefinition>,
+}
+
+impl SourceMap {
+    pub fn instruction_on_line(&self, line: usize) -> Option<usize> {
+        self.instructions.iter().position(|i| i.line == line)
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<&SymbolDefinition> {
+        self.symbols
+            .iter()
+            .find(|definition| definition.name == name)
+    }
+}
+
+struct PendingInstruction<'a> {
+    op: Opcode,
+    argument: &'a str,
+    line: usize,
+    op_column: usize,
+    column: usize,
+}
+
+fn error(kind: ParseErrorKind, line: usize, column: usize, text: &str) -> ParseError {
+    ParseError {
+        kind,
+        line,
+        column,
+        text: String::from(text),
+    }
+}
+
+fn is_symbol_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && name.parse::<Opcode>().is_err()
+        && name != "const"
+}
+
+fn define<'a>(
+    symbols: &mut HashMap<&'a str, Symbol>,
+    name: &'a str,
+    symbol: Symbol,
+    line: usize,
+    column: usize,
+) -> Result<(), ParseError> {
+    if !is_symbol_name(name) {
+        return Err(error(ParseErrorKind::InvalidSymbol, line, column, name));
+    }
+    if symbols.insert(name, symbol).is_some() {
+        return Err(error(ParseErrorKind::DuplicateSymbol, line, column, name));
+    }
+    Ok(())
+}
+
+fn next_token<'a>(
+    tokens: &mut impl Iterator<Item = (usize, &'a str)>,
+    line: &str,
+    line_number: usize,
+) -> Result<(usize, &'a str), ParseError> {
+    tokens.next().ok_or_else(|| {
+        let end = line.trim_end().chars().count() + 1;
+        error(
+            ParseErrorKind::MissingArgument,
+            line_number,
+            end,
+            line.trim(),
+        )
+    })
+}
+
+fn expect_end<'a>(
+    tokens: &mut impl Iterator<Item = (usize, &'a str)>,
+    line_number: usize,
+) -> Result<(), ParseError> {
+    match tokens.next() {
+        Some((column, text)) => Err(error(
+            ParseErrorKind::TrailingInput,
+            line_number,
+            column,
+            text,
+        )),
+        None => Ok(()),
+    }
+}
+
+pub fn assemble(source: &str) -> Result<Program, ParseError> {
+    assemble_mapped(source).map(|(program, _)| program)
+}
+
+// assemble, also returning where each instruction and symbol is in the source
+pub fn assemble_mapped(source: &str) -> Result<(Program, SourceMap), ParseError> {
+    let mut symbols = HashMap::<&str, Symbol>::new();
+    let mut definitions = Vec::<SymbolDefinition>::new();
+    let mut pending = Vec::<PendingInstruction>::new();
+
+    // first pass collects symbols, so labels can be used before they're defined
+    for (i, raw_line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let line = raw_line.split('#').next().unwrap_or("");
+        let mut tokens = tokens(line).peekable();
+
+        if let Some(&(_, "const")) = tokens.peek() {
+            tokens.next();
+            let (name_column, name) = next_token(&mut tokens, line, line_number)?;
+            let (equals_column, equals) = next_token(&mut tokens, line, line_number)?;
+            if equals != "=" {
+                return Err(error(
+                    ParseErrorKind::InvalidArgument,
+                    line_number,
+                    equals_column,
+                    equals,
+                ));
+            }
+            let (value_column, value_text) = next_token(&mut tokens, line, line_number)?;
+            let value = value_text.parse::<i32>().map_err(|_| {
+                error(
+                    ParseErrorKind::InvalidArgument,
+                    line_number,
+                    value_column,
+                    value_text,
+                )
+            })?;
+            expect_end(&mut tokens, line_number)?;
+            define(
+                &mut symbols,
+                name,
+                Symbol::Constant(value),
+                line_number,
+                name_column,
+            )?;
+            definitions.push(SymbolDefinition {
+                name: String::from(name),
+                symbol: Symbol::Constant(value),
+                line: line_number,
+                column: name_column,
+            });
+            continue;
+        }
+
+        while let Some(&(column, token)) = tokens.peek() {
+            match token.strip_suffix(':') {
+                Some(label) => {
+                    define(
+                        &mut symbols,
+                        label,
+                        Symbol::Label(pending.len()),
+                        line_number,
+                        column,
+                    )?;
+                    definitions.push(SymbolDefinition {
+                        name: String::from(label),
+                        symbol: Symbol::Label(pending.len()),
+                        line: line_number,
+                        column,
+                    });
+                    tokens.next();
+                }
+                None => break,
+            }
+        }
+
+        let (op_column, op_text) = match tokens.next() {
+            Some(token) => token,
+            None => continue,
+        };
+        let op = op_text
+            .parse::<Opcode>()
+            .map_err(|_| error(ParseErrorKind::UnknownOp, line_number, op_column, op_text))?;
+        let (column, argument) = next_token(&mut tokens, line, line_number)?;
+        expect_end(&mut tokens, line_number)?;
+        pending.push(PendingInstruction {
+            op,
+            argument,
+            line: line_number,
+            op_column,
+            column,
+        });
+    }
+
+    let instructions = pending
+        .iter()
+        .enumerate()
+        .map(|(pc, p)| {
+            let value = match p.argument.parse::<i32>() {
+                Ok(value) => value,
+                Err(_) => match symbols.get(p.argument) {
+                    Some(Symbol::Constant(value)) => *value,
+                    Some(Symbol::Label(target)) => *target as i32 - pc as i32,
+                    None => {
+                        let kind = if is_symbol_name(p.argument) {
+                            ParseErrorKind::UndefinedSymbol
+                        } else {
+                            ParseErrorKind::InvalidArgument
+                        };
+                        return Err(error(kind, p.line, p.column, p.argument));
+                    }
+                },
+            };
+            Ok(Instruction { op: p.op, value })
+        })
+        .collect::<Result<Vec<Instruction>, ParseError>>()?;
+
+    let map = SourceMap {
+        instructions: pending
+            .iter()
+            .map(|p| SourceInstruction {
+                line: p.line,
+                column: p.op_column,
+                argument: String::from(p.argument),
+                argument_column: p.column,
+            })
+            .collect(),
+        symbols: definitions,
+    };
+    Ok((Program::new(instructions), map))
+}
+
+// canonical `op +N` text, the same as the program's Display
+pub fn disassemble(program: &Program) -> String {
+    program.to_string()
+}
+
+// like disassemble, but every in-bounds jmp target gets an `L<pc>:` label and jmps refer to it.
+// assembles back to the same program
+pub fn disassemble_labelled(program: &Program) -> String {
+    let len = program.instructions.len();
+    let target = |pc: usize, instruction: &Instruction| {
+        let target = pc as i64 + instruction.value as i64;
+        if instruction.op == Opcode::Jmp && target >= 0 && target <= len as i64 {
+            Some(target as usize)
+        } else {
+            None
+        }
+    };
+
+    let mut labelled = vec![false; len + 1];
+    for (pc, instruction) in program.instructions.iter().enumerate() {
+        if let Some(target) = target(pc, instruction) {
+            labelled[target] = true;
+        }
+    }
+
+    let mut text = String::new();
+    for (pc, instruction) in program.instructions.iter().enumerate() {
+        if labelled[pc] {
+            text += &format!("L{}:\n", pc);
+        }
+        match target(pc, instruction) {
+            Some(target) => text += &format!("    {} L{}\n", instruction.op, target),
+            None => text += &format!("    {}\n", instruction),
+        }
+    }
+    if labelled[len] {
+        text += &format!("L{}:\n", len);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_support::instruction_with;
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn labels_and_constants() {
+        let source = "
+            # count down from start
+            const start = 3
+
+            acc start
+            top: acc -1   # decrement
+                 jmp skip
+                 jmp top
+            skip:
+            done:
+        ";
+        let program = assemble(source).unwrap();
+        assert_eq!(
+            program,
+            Program::parse("acc +3\nacc -1\njmp +2\njmp -2").unwrap()
+        );
+        assert_eq!(program.run().into_state().accumulator, 2);
+    }
+
+    #[test]
+    fn assembler_errors() {
+        let error = assemble("jmp nowhere").err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::UndefinedSymbol);
+        assert_eq!((error.line, error.column), (1, 5));
+
+        let error = assemble("a: nop +0\na: nop +0").err().unwrap();
+        assert_eq!(
+            (error.kind, error.line),
+            (ParseErrorKind::DuplicateSymbol, 2)
+        );
+
+        let error = assemble("const jmp = 1").err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::InvalidSymbol);
+
+        let error = assemble("acc 1x").err().unwrap();
+        assert_eq!(error.kind, ParseErrorKind::InvalidArgument);
+    }
+
+    #[test]
+    fn source_map() {
+        let (_, map) = assemble_mapped("const step = 2\n\ntop:  acc step\n      jmp top").unwrap();
+        assert_eq!(
+            map.instructions[1],
+            SourceInstruction {
+                line: 4,
+                column: 7,
+                argument: String::from("top"),
+                argument_column: 11
+            }
+        );
+        assert_eq!(map.instruction_on_line(3), Some(0));
+        assert_eq!(map.instruction_on_line(2), None);
+        let top = map.symbol("top").unwrap();
+        assert_eq!((top.symbol, top.line, top.column), (Symbol::Label(0), 3, 1));
+        assert_eq!(map.symbol("step").unwrap().symbol, Symbol::Constant(2));
+    }
+
+    #[test]
+    fn labelled_disassembly() {
+        let program = Program::parse("nop +0\njmp +2\njmp -1\nacc +1\njmp +9").unwrap();
+        let text = disassemble_labelled(&program);
+        assert_eq!(
+            text,
+            "    nop +0\nL1:\n    jmp L3\n    jmp L1\nL3:\n    acc +1\n    jmp +9\n"
+        );
+        assert_eq!(assemble(&text).unwrap(), program);
+    }
+
+    // source text for a program with arbitrary spacing and signs, as people write it
+    fn source() -> impl Strategy<Value = String> {
+        prop::collection::vec(
+            (
+                instruction_with(any::<i32>()),
+                " {0,3}",
+                " {1,3}",
+                any::<bool>(),
+            ),
+            0..50,
+        )
+        .prop_map(|lines| {
+            lines
+                .iter()
+                .map(|(instruction, indent, gap, explicit_sign)| {
+                    let value = if *explicit_sign || instruction.value < 0 {
+                        format!("{:+}", instruction.value)
+                    } else {
+                        instruction.value.to_string()
+                    };
+                    format!("{}{}{}{}", indent, instruction.op, gap, value)
+                })
+                .collect::<Vec<String>>()
+                .join("\n")
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn parse_print_parse(source in source()) {
+            let program = Program::parse(&source).unwrap();
+            let printed = disassemble(&program);
+            prop_assert_eq!(&Program::parse(&printed).unwrap(), &program);
+            prop_assert_eq!(disassemble(&Program::parse(&printed).unwrap()), printed);
+            prop_assert_eq!(&assemble(&source).unwrap(), &program);
+        }
+
+        #[test]
+        fn labelled_round_trip(instructions in prop::collection::vec(instruction_with(any::<i32>()), 0..50)) {
+            let program = Program::new(instructions);
+            prop_assert_eq!(assemble(&disassemble_labelled(&program)).unwrap(), program);
+        }
+    }
+}
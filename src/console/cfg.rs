@@ -0,0 +1,416 @@
+use super::{Opcode, Program};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
+// a single nop <-> jmp swap that makes the program halt, and the accumulator it halts with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Repair {
+    pub pc: usize,
+    pub from: Opcode,
+    pub to: Opcode,
+    pub accumulator: i32,
+}
+
+impl Repair {
+    pub fn apply(&self, program: &mut Program) {
+        program.instructions[self.pc].op = self.to;
+    }
+}
+
+// control flow graph of a program. node `len` is the virtual exit one past the last
+// instruction, and a jump out of bounds has no successor
+pub struct ControlFlowGraph<'a> {
+    program: &'a Program,
+    successors: Vec<Option<usize>>,
+    predecessors: Vec<Vec<usize>>,
+    reaches_end: Vec<bool>,
+    // accumulator change from a node to the exit, only meaningful where reaches_end is set
+    acc_to_end: Vec<i64>,
+    // lowest and highest the accumulator gets relative to its value at the node, on the way
+    // from there to the exit
+    acc_range_to_end: Vec<(i64, i64)>,
+    // set where the way to the exit has no `in`, which would replace the accumulator with
+    // whatever it reads
+    no_input_to_end: Vec<bool>,
+    // instructions executed from pc 0, see execution_path
+    path: Vec<usize>,
+    // position on the path of the first path node the way from a node to the exit runs into
+    rejoins_path_at: Vec<Option<usize>>,
+}
+
+// where the instruction at pc goes next, None if that leaves 0..=len
+pub(super) fn target(len: usize, pc: usize, op: Opcode, value: i32) -> Option<usize> {
+    let target = match op {
+        Opcode::Nop | Opcode::Acc | Opcode::In | Opcode::Out => pc as i64 + 1,
+        Opcode::Jmp => pc as i64 + value as i64,
+    };
+    if target < 0 || target > len as i64 {
+        None
+    } else {
+        Some(target as usize)
+    }
+}
+
+fn swapped(op: Opcode) -> Option<Opcode> {
+    match op {
+        Opcode::Nop => Some(Opcode::Jmp),
+        Opcode::Jmp => Some(Opcode::Nop),
+        Opcode::Acc | Opcode::In | Opcode::Out => None,
+    }
+}
+
+impl<'a> ControlFlowGraph<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        let len = program.instructions.len();
+        let successors = program
+            .instructions
+            .iter()
+            .enumerate()
+            .map(|(pc, instruction)| target(len, pc, instruction.op, instruction.value))
+            .chain(std::iter::once(None))
+            .collect::<Vec<Option<usize>>>();
+
+        let mut predecessors = vec![Vec::<usize>::new(); len + 1];
+        for (pc, successor) in successors.iter().enumerate() {
+            if let Some(successor) = successor {
+                predecessors[*successor].push(pc);
+            }
+        }
+
+        let mut path = Vec::<usize>::new();
+        let mut position = vec![None; len + 1];
+        let mut pc = 0;
+        while pc < len && position[pc].is_none() {
+            position[pc] = Some(path.len());
+            path.push(pc);
+            match successors[pc] {
+                Some(next) => pc = next,
+                None => break,
+            }
+        }
+
+        // reverse reachability from the exit. every node has a single successor, so the bfs
+        // visits a node only after the node it flows into and the accumulator can be summed as we go
+        let mut reaches_end = vec![false; len + 1];
+        let mut acc_to_end = vec![0i64; len + 1];
+        let mut acc_range_to_end = vec![(0i64, 0i64); len + 1];
+        let mut no_input_to_end = vec![false; len + 1];
+        no_input_to_end[len] = true;
+        let mut rejoins_path_at = vec![None; len + 1];
+        let mut queue = VecDeque::new();
+        reaches_end[len] = true;
+        queue.push_back(len);
+        while let Some(node) = queue.pop_front() {
+            for &pc in &predecessors[node] {
+                if !reaches_end[pc] {
+                    reaches_end[pc] = true;
+                    let instruction = &program.instructions[pc];
+                    let change = match instruction.op {
+                        Opcode::Acc => instruction.value as i64,
+                        _ => 0,
+                    };
+                    acc_to_end[pc] = acc_to_end[node] + change;
+                    let (lowest, highest) = acc_range_to_end[node];
+                    acc_range_to_end[pc] = ((change + lowest).min(0), (change + highest).max(0));
+                    no_input_to_end[pc] = no_input_to_end[node] && instruction.op != Opcode::In;
+                    rejoins_path_at[pc] = position[pc].or(rejoins_path_at[node]);
+                    queue.push_back(pc);
+                }
+            }
+        }
+
+        ControlFlowGraph {
+            program,
+            successors,
+            predecessors,
+            reaches_end,
+            acc_to_end,
+            acc_range_to_end,
+            no_input_to_end,
+            path,
+            rejoins_path_at,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.program.instructions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.program.instructions.is_empty()
+    }
+
+    pub fn successor(&self, pc: usize) -> Option<usize> {
+        self.successors.get(pc).copied().flatten()
+    }
+
+    pub fn predecessors(&self, pc: usize) -> &[usize] {
+        &self.predecessors[pc]
+    }
+
+    pub fn reaches_end(&self, pc: usize) -> bool {
+        self.reaches_end.get(pc).copied().unwrap_or(false)
+    }
+
+    // instructions from which the unmodified program halts
+    pub fn terminating_instructions(&self) -> Vec<usize> {
+        (0..self.len()).filter(|&pc| self.reaches_end[pc]).collect()
+    }
+
+    // instructions executed from pc 0, in order, until the program halts, loops or jumps out of bounds
+    pub fn execution_path(&self) -> Vec<usize> {
+        self.path.clone()
+    }
+
+    // every cycle, each as the instructions on it starting from the lowest. with one successor
+    // each, following every instruction until the walk runs into itself (a new cycle), an earlier
+    // walk or the end finds every cycle once
+    pub fn cycles(&self) -> Vec<Vec<usize>> {
+        let len = self.len();
+        let mut walked = vec![None; len];
+        let mut cycles = Vec::<Vec<usize>>::new();
+        for first in 0..len {
+            let mut path = Vec::<usize>::new();
+            let mut pc = first;
+            let closed = loop {
+                if pc >= len {
+                    break false;
+                }
+                if let Some(walk) = walked[pc] {
+                    break walk == first;
+                }
+                walked[pc] = Some(first);
+                path.push(pc);
+                match self.successor(pc) {
+                    Some(next) => pc = next,
+                    None => break false,
+                }
+            };
+            if closed {
+                let start = path.iter().position(|&p| p == pc).unwrap();
+                let mut cycle = path[start..].to_vec();
+                let lowest = (0..cycle.len()).min_by_key(|&i| cycle[i]).unwrap();
+                cycle.rotate_left(lowest);
+                cycles.push(cycle);
+            }
+        }
+        cycles
+    }
+
+    // every single nop <-> jmp swap that makes a non-halting program halt, in execution order.
+    // swaps off the execution path change nothing, and swaps on it need the swapped target to
+    // reach the exit in the original graph without coming back through the swapped instruction.
+    // when the path loops that's a given, nothing on it reaches the exit, but when it reaches the
+    // exit and traps on the way, a route that rejoins the path at or before the swap runs into it
+    // again and loops. the vm traps as soon as the accumulator overflows, so a swap only counts if it stays in i32
+    // the whole way to the exit, not just at the end. `in` makes the accumulator depend on what's
+    // read, so swaps are only looked for before the first one on the path, and only count if the
+    // way from the swap to the exit reads nothing. returns nothing if the program already halts,
+    // meaning its path reaches the exit and doesn't overflow before then (or before its first `in`)
+    pub fn repairs(&self) -> Vec<Repair> {
+        let len = self.len();
+        if len == 0 {
+            return Vec::new();
+        }
+        let fits = |accumulator: i64| i32::try_from(accumulator).is_ok();
+
+        let mut repairs = Vec::<Repair>::new();
+        let mut accumulator = 0i64;
+        let mut trapped = false;
+        for (position, &pc) in self.path.iter().enumerate() {
+            let instruction = &self.program.instructions[pc];
+            if let Some(to) = swapped(instruction.op) {
+                if let Some(next) = target(len, pc, to, instruction.value) {
+                    let (lowest, highest) = self.acc_range_to_end[next];
+                    let loops_back = self.rejoins_path_at[next].is_some_and(|at| at <= position);
+                    if self.reaches_end[next]
+                        && !loops_back
+                        && self.no_input_to_end[next]
+                        && fits(accumulator + lowest)
+                        && fits(accumulator + highest)
+                    {
+                        repairs.push(Repair {
+                            pc,
+                            from: instruction.op,
+                            to,
+                            accumulator: (accumulator + self.acc_to_end[next]) as i32,
+                        });
+                    }
+                }
+            }
+            if instruction.op == Opcode::In {
+                break;
+            }
+            if instruction.op == Opcode::Acc {
+                accumulator += instruction.value as i64;
+                // the unmodified program traps here, so nothing later on the path runs
+                if !fits(accumulator) {
+                    trapped = true;
+                    break;
+                }
+            }
+        }
+        // reaching the exit in the graph isn't enough, the run can trap on the way there
+        if self.reaches_end(0) && !trapped {
+            return Vec::new();
+        }
+        repairs
+    }
+}
+
+impl Program {
+    pub fn repairs(&self) -> Vec<Repair> {
+        ControlFlowGraph::new(self).repairs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_support::{instruction, SAMPLE};
+    use super::super::{ProgramState, Termination};
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn reachability() {
+        let program = Program::parse(SAMPLE).unwrap();
+        let cfg = ControlFlowGraph::new(&program);
+        assert_eq!(cfg.terminating_instructions(), vec![8]);
+        assert_eq!(cfg.execution_path(), vec![0, 1, 2, 6, 7, 3, 4]);
+        assert_eq!(cfg.predecessors(6), &[2, 5]);
+    }
+
+    #[test]
+    fn sample_repairs() {
+        let program = Program::parse(SAMPLE).unwrap();
+        assert_eq!(
+            program.repairs(),
+            vec![Repair {
+                pc: 7,
+                from: Opcode::Jmp,
+                to: Opcode::Nop,
+                accumulator: 8
+            }]
+        );
+    }
+
+    #[test]
+    fn every_repair() {
+        // swapping either jmp escapes the loop, each with a different accumulator
+        let program = Program::parse("acc +1\njmp +2\njmp +3\nacc +10\njmp -1\nacc +100").unwrap();
+        let repairs = program.repairs();
+        assert_eq!(
+            repairs
+                .iter()
+                .map(|r| (r.pc, r.accumulator))
+                .collect::<Vec<(usize, i32)>>(),
+            vec![(1, 101), (4, 111)]
+        );
+        for repair in repairs {
+            let mut fixed = program.clone();
+            repair.apply(&mut fixed);
+            assert_eq!(fixed.run().into_state().accumulator, repair.accumulator);
+        }
+    }
+
+    #[test]
+    fn overflow_on_the_way() {
+        // both swaps end at i32::MAX - 4, but pass through i32::MAX + 1 getting there
+        let program = Program::parse("nop +2\njmp +0\nacc +2147483647\nacc +1\nacc -5").unwrap();
+        assert_eq!(program.repairs(), Vec::<Repair>::new());
+        assert!(!program.fix_and_run().is_halted());
+
+        // and a path that overflows before it gets to the swap
+        let program = Program::parse("acc +2147483647\nacc +1\nnop +2\njmp +0").unwrap();
+        assert_eq!(program.repairs(), Vec::<Repair>::new());
+
+        // reaches the exit but traps at pc 4 first, jumping over the acc -3 halts with i32::MIN
+        let program =
+            Program::parse("nop +3\nacc -3\nnop -5\nnop -2147483648\nacc -2147483648").unwrap();
+        assert!(matches!(
+            program.run(),
+            Termination::InvalidInstruction { pc: 4, .. }
+        ));
+        assert_eq!(
+            program.repairs(),
+            vec![Repair {
+                pc: 0,
+                from: Opcode::Nop,
+                to: Opcode::Jmp,
+                accumulator: i32::MIN
+            }]
+        );
+        match program.fix_and_run() {
+            Termination::Halted(state) => assert_eq!(state.accumulator, i32::MIN),
+            other => panic!("unexpected termination {:?}", other),
+        }
+
+        // the path reaches the exit and traps at pc 4. swapping pc 1 jumps back to pc 0, whose
+        // way to the exit runs through pc 1 again, so only skipping the acc +2147483647 works
+        let program = Program::parse("acc -1\nnop -1\nnop +3\nacc +2147483647\nacc +2").unwrap();
+        assert_eq!(
+            program.repairs(),
+            vec![Repair {
+                pc: 2,
+                from: Opcode::Nop,
+                to: Opcode::Jmp,
+                accumulator: -1
+            }]
+        );
+        assert!(program.fix_and_run().is_halted());
+    }
+
+    #[test]
+    fn input() {
+        // the jmp +0 swap would halt only after reading, with an accumulator nothing here knows
+        let program = Program::parse("jmp +0\nin +0\nacc +1").unwrap();
+        assert_eq!(program.repairs(), Vec::<Repair>::new());
+
+        // swaps after an `in` on the path are never looked for
+        let program = Program::parse("in +0\njmp +0").unwrap();
+        assert_eq!(program.repairs(), Vec::<Repair>::new());
+
+        // a read the swap skips over doesn't matter
+        let program = Program::parse("nop +3\njmp +0\nin +0\nacc +1").unwrap();
+        assert_eq!(
+            program.repairs(),
+            vec![Repair {
+                pc: 0,
+                from: Opcode::Nop,
+                to: Opcode::Jmp,
+                accumulator: 1
+            }]
+        );
+    }
+
+    proptest! {
+        // every swap that makes the program halt, found by swapping and running
+        #[test]
+        fn matches_brute_force(instructions in prop::collection::vec(instruction(), 1..30)) {
+            let program = Program::new(instructions);
+            let mut expected = Vec::<(usize, i32)>::new();
+            if !program.run().is_halted() {
+                for pc in 0..program.len() {
+                    let mut swapped = program.clone();
+                    swapped.twiddle(&ProgramState {
+                        current_instruction: pc,
+                        ..ProgramState::new()
+                    });
+                    if swapped == program {
+                        continue;
+                    }
+                    if let Termination::Halted(state) = swapped.run() {
+                        expected.push((pc, state.accumulator));
+                    }
+                }
+            }
+            let mut found = program
+                .repairs()
+                .iter()
+                .map(|r| (r.pc, r.accumulator))
+                .collect::<Vec<(usize, i32)>>();
+            found.sort_unstable();
+            prop_assert_eq!(found, expected);
+        }
+    }
+}
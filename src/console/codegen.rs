@@ -0,0 +1,453 @@
+use super::cfg::target;
+use super::{Opcode, Program};
+use std::fmt::{self, Write};
+
+// turns a program into a standalone function with no dependency on this crate, so a checked
+// program can be built into something else without the interpreter. the function runs the
+// program from pc 0 with the accumulator at 0 and stops the same way Program::run does:
+//
+//   rust: `pub fn name() -> Result<i32, (&'static str, i32)>`, Ok with the accumulator when it
+//         halts, otherwise Err with "infinite loop", "out of bounds" or "overflow" and the
+//         accumulator at that point
+//   c:    `int name(int32_t *acc)`, returning CONSOLE_HALTED (0), CONSOLE_INFINITE_LOOP (1),
+//         CONSOLE_OUT_OF_BOUNDS (2) or CONSOLE_OVERFLOW (3) and storing the accumulator in *acc.
+//         the visited flags are a bitset on the stack, one byte per 8 instructions
+//
+// `in` and `out` have nothing to talk to outside the vm, so programs using them are rejected
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodegenError {
+    InvalidName(String),
+    UnsupportedInstruction { pc: usize, op: Opcode },
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CodegenError::InvalidName(name) => write!(f, "invalid function name {:?}", name),
+            CodegenError::UnsupportedInstruction { pc, op } => {
+                write!(
+                    f,
+                    "{} at {} can't be compiled to a standalone function",
+                    op, pc
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for CodegenError {}
+
+// keywords of either language, and names the generated code already uses, so one name works
+// for both targets
+const RESERVED: &[&str] = &[
+    // rust
+    "Self",
+    "abstract",
+    "as",
+    "async",
+    "await",
+    "become",
+    "box",
+    "break",
+    "const",
+    "continue",
+    "crate",
+    "do",
+    "dyn",
+    "else",
+    "enum",
+    "extern",
+    "false",
+    "final",
+    "fn",
+    "for",
+    "gen",
+    "if",
+    "impl",
+    "in",
+    "let",
+    "loop",
+    "macro",
+    "match",
+    "mod",
+    "move",
+    "mut",
+    "override",
+    "priv",
+    "pub",
+    "ref",
+    "return",
+    "self",
+    "static",
+    "struct",
+    "super",
+    "trait",
+    "true",
+    "try",
+    "type",
+    "typeof",
+    "union",
+    "unsafe",
+    "unsized",
+    "use",
+    "virtual",
+    "where",
+    "while",
+    "yield",
+    "Ok",
+    "Err",
+    // c
+    "auto",
+    "bool",
+    "case",
+    "char",
+    "default",
+    "double",
+    "float",
+    "goto",
+    "inline",
+    "int",
+    "long",
+    "register",
+    "restrict",
+    "short",
+    "signed",
+    "sizeof",
+    "switch",
+    "typedef",
+    "unsigned",
+    "void",
+    "volatile",
+    "int32_t",
+    "INT32_MAX",
+    "INT32_MIN",
+    "CONSOLE_HALTED",
+    "CONSOLE_INFINITE_LOOP",
+    "CONSOLE_OUT_OF_BOUNDS",
+    "CONSOLE_OVERFLOW",
+    // both
+    "main",
+];
+
+fn check(program: &Program, name: &str) -> Result<(), CodegenError> {
+    let mut chars = name.chars();
+    let valid = match chars.next() {
+        Some(first) => {
+            (first.is_ascii_alphabetic() || first == '_')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+                && name != "_"
+                // c reserves these for the implementation
+                && !name.starts_with("__")
+                && !(name.starts_with('_') && name[1..].starts_with(|c: char| c.is_ascii_uppercase()))
+                && !RESERVED.contains(&name)
+        }
+        None => false,
+    };
+    if !valid {
+        return Err(CodegenError::InvalidName(name.to_string()));
+    }
+    match program
+        .instructions()
+        .iter()
+        .position(|instruction| matches!(instruction.op, Opcode::In | Opcode::Out))
+    {
+        Some(pc) => Err(CodegenError::UnsupportedInstruction {
+            pc,
+            op: program.instructions()[pc].op,
+        }),
+        None => Ok(()),
+    }
+}
+
+pub fn to_rust(program: &Program, name: &str) -> Result<String, CodegenError> {
+    check(program, name)?;
+    let len = program.len();
+    let mut out = String::new();
+    // writing to a String can't fail. an empty program leaves the loop body unused
+    let _ = writeln!(out, "#[allow(unused_mut, unreachable_code)]");
+    let _ = writeln!(
+        out,
+        "pub fn {}() -> Result<i32, (&'static str, i32)> {{",
+        name
+    );
+    let _ = writeln!(out, "    let mut acc: i32 = 0;");
+    let _ = writeln!(out, "    let mut pc: usize = 0;");
+    let _ = writeln!(out, "    let mut visited = vec![false; {}];", len);
+    let _ = writeln!(out, "    loop {{");
+    let _ = writeln!(out, "        if pc == {} {{", len);
+    let _ = writeln!(out, "            return Ok(acc);");
+    let _ = writeln!(out, "        }}");
+    let _ = writeln!(out, "        if visited[pc] {{");
+    let _ = writeln!(out, "            return Err((\"infinite loop\", acc));");
+    let _ = writeln!(out, "        }}");
+    let _ = writeln!(out, "        visited[pc] = true;");
+    let _ = writeln!(out, "        pc = match pc {{");
+    for (pc, instruction) in program.instructions().iter().enumerate() {
+        let body = match instruction.op {
+            Opcode::Acc => format!(
+                "match acc.checked_add({}) {{\n                Some(sum) => {{\n                    acc = sum;\n                    {}\n                }}\n                None => return Err((\"overflow\", acc)),\n            }}",
+                instruction.value,
+                pc + 1
+            ),
+            Opcode::Jmp => match target(len, pc, Opcode::Jmp, instruction.value) {
+                Some(target) => target.to_string(),
+                None => String::from("return Err((\"out of bounds\", acc))"),
+            },
+            _ => (pc + 1).to_string(),
+        };
+        let _ = writeln!(out, "            // {}", instruction);
+        let _ = writeln!(out, "            {} => {},", pc, body);
+    }
+    let _ = writeln!(out, "            _ => unreachable!(),");
+    let _ = writeln!(out, "        }};");
+    let _ = writeln!(out, "    }}");
+    let _ = writeln!(out, "}}");
+    Ok(out)
+}
+
+// straight-line code with gotos for jmps, labels only where something jumps to (an unused label
+// is a warning)
+pub fn to_c(program: &Program, name: &str) -> Result<String, CodegenError> {
+    check(program, name)?;
+    let len = program.len();
+    let mut labelled = vec![false; len + 1];
+    for (pc, instruction) in program.instructions().iter().enumerate() {
+        if instruction.op == Opcode::Jmp {
+            if let Some(target) = target(len, pc, Opcode::Jmp, instruction.value) {
+                labelled[target] = true;
+            }
+        }
+    }
+    let mut out = String::new();
+    let _ = writeln!(out, "#include <stdint.h>");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "#ifndef CONSOLE_HALTED");
+    let _ = writeln!(out, "#define CONSOLE_HALTED 0");
+    let _ = writeln!(out, "#define CONSOLE_INFINITE_LOOP 1");
+    let _ = writeln!(out, "#define CONSOLE_OUT_OF_BOUNDS 2");
+    let _ = writeln!(out, "#define CONSOLE_OVERFLOW 3");
+    let _ = writeln!(out, "#endif");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "int {}(int32_t *acc_out) {{", name);
+    let _ = writeln!(out, "    int32_t acc = 0;");
+    // a zero length array isn't valid c
+    let _ = writeln!(
+        out,
+        "    unsigned char visited[{}] = {{0}};",
+        len.div_ceil(8).max(1)
+    );
+    let _ = writeln!(out, "    (void)visited;");
+    for (pc, instruction) in program.instructions().iter().enumerate() {
+        if labelled[pc] {
+            let _ = writeln!(out, "l{}:", pc);
+        }
+        let _ = writeln!(out, "    /* {} */", instruction);
+        let (byte, bit) = (pc / 8, 1 << (pc % 8));
+        let _ = writeln!(
+            out,
+            "    if (visited[{}] & {}) {{ *acc_out = acc; return CONSOLE_INFINITE_LOOP; }}",
+            byte, bit
+        );
+        let _ = writeln!(out, "    visited[{}] |= {};", byte, bit);
+        let value = instruction.value;
+        match instruction.op {
+            Opcode::Acc => {
+                // compare against the bound instead of adding, signed overflow is undefined
+                let overflows = if value >= 0 {
+                    format!("acc > INT32_MAX - {}", value)
+                } else {
+                    // written as a sum so i32::MIN never appears as a literal
+                    format!("acc < INT32_MIN + {}", -(value as i64))
+                };
+                let _ = writeln!(
+                    out,
+                    "    if ({}) {{ *acc_out = acc; return CONSOLE_OVERFLOW; }}",
+                    overflows
+                );
+                let _ = writeln!(out, "    acc += {};", value as i64);
+            }
+            Opcode::Jmp => match target(len, pc, Opcode::Jmp, value) {
+                Some(target) => {
+                    let _ = writeln!(out, "    goto l{};", target);
+                }
+                None => {
+                    let _ = writeln!(out, "    *acc_out = acc; return CONSOLE_OUT_OF_BOUNDS;");
+                }
+            },
+            _ => {}
+        }
+    }
+    if labelled[len] {
+        let _ = writeln!(out, "l{}:", len);
+    }
+    let _ = writeln!(out, "    *acc_out = acc;");
+    let _ = writeln!(out, "    return CONSOLE_HALTED;");
+    let _ = writeln!(out, "}}");
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_support::SAMPLE;
+    use super::super::Termination;
+    use super::*;
+    use std::path::PathBuf;
+    use std::process::Command;
+    use std::{env, fs};
+
+    fn programs() -> Vec<Program> {
+        vec![
+            SAMPLE,
+            &SAMPLE.replace("jmp -4", "nop -4"),
+            "",
+            "acc +3\njmp +5",
+            "acc -7\njmp -2",
+            "acc +2147483647\nacc +1",
+            "acc -2147483648\nacc -2147483648",
+            "acc -2147483648\nacc +2147483647\nacc +1",
+        ]
+        .into_iter()
+        .map(|source| Program::parse(source).unwrap())
+        .collect()
+    }
+
+    // what the generated code should print: status and accumulator
+    fn expected(program: &Program) -> String {
+        let termination = program.run();
+        let status = match termination {
+            Termination::Halted(_) => 0,
+            Termination::InfiniteLoop { .. } => 1,
+            Termination::OutOfBounds { .. } => 2,
+            Termination::InvalidInstruction { .. } => 3,
+            _ => unreachable!(),
+        };
+        format!("{} {}", status, termination.state().accumulator)
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("console-codegen-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn run(binary: PathBuf) -> Vec<String> {
+        let output = Command::new(binary).output().unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn rust() {
+        let programs = programs();
+        let mut source = String::new();
+        let mut main = String::from("fn main() {\n");
+        for (i, program) in programs.iter().enumerate() {
+            source += &to_rust(program, &format!("program_{}", i)).unwrap();
+            main += &format!(
+                "    match program_{}() {{\n        Ok(acc) => println!(\"0 {{}}\", acc),\n        Err((\"infinite loop\", acc)) => println!(\"1 {{}}\", acc),\n        Err((\"out of bounds\", acc)) => println!(\"2 {{}}\", acc),\n        Err((_, acc)) => println!(\"3 {{}}\", acc),\n    }}\n",
+                i
+            );
+        }
+        source += &main;
+        source += "}\n";
+
+        let dir = scratch_dir("rust");
+        fs::write(dir.join("main.rs"), source).unwrap();
+        let status = Command::new(env::var("RUSTC").unwrap_or_else(|_| String::from("rustc")))
+            .arg("-O")
+            .arg("--edition=2018")
+            .arg("-o")
+            .arg(dir.join("main"))
+            .arg(dir.join("main.rs"))
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let results = run(dir.join("main"));
+        fs::remove_dir_all(dir).unwrap();
+        assert_eq!(
+            results,
+            programs.iter().map(expected).collect::<Vec<String>>()
+        );
+    }
+
+    #[test]
+    fn c() {
+        let programs = programs();
+        let mut source = String::new();
+        let mut main = String::from("#include <stdio.h>\n\nint main(void) {\n    int32_t acc;\n");
+        // everything runs twice, the second call starts from fresh visited flags
+        for (i, program) in programs.iter().enumerate() {
+            source += &to_c(program, &format!("program_{}", i)).unwrap();
+            for _ in 0..2 {
+                main += &format!(
+                    "    {{ int status = program_{}(&acc); printf(\"%d %d\\n\", status, (int)acc); }}\n",
+                    i
+                );
+            }
+        }
+        source += &main;
+        source += "    return 0;\n}\n";
+
+        let dir = scratch_dir("c");
+        fs::write(dir.join("main.c"), source).unwrap();
+        let status = Command::new(env::var("CC").unwrap_or_else(|_| String::from("cc")))
+            .arg("-O2")
+            .arg("-std=c99")
+            .arg("-Wall")
+            .arg("-Werror")
+            .arg("-o")
+            .arg(dir.join("main"))
+            .arg(dir.join("main.c"))
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let results = run(dir.join("main"));
+        fs::remove_dir_all(dir).unwrap();
+        assert_eq!(
+            results,
+            programs
+                .iter()
+                .flat_map(|program| vec![expected(program); 2])
+                .collect::<Vec<String>>()
+        );
+    }
+
+    #[test]
+    fn rejected() {
+        let program = Program::parse("acc +1\nout +0").unwrap();
+        assert_eq!(
+            to_c(&program, "f"),
+            Err(CodegenError::UnsupportedInstruction {
+                pc: 1,
+                op: Opcode::Out
+            })
+        );
+        let program = Program::parse("acc +1").unwrap();
+        for name in &[
+            "",
+            "_",
+            "1up",
+            "two words",
+            "fn",
+            "int",
+            "main",
+            "__f",
+            "_Bool",
+        ] {
+            assert_eq!(
+                to_rust(&program, name),
+                Err(CodegenError::InvalidName(name.to_string()))
+            );
+            assert_eq!(
+                to_c(&program, name),
+                Err(CodegenError::InvalidName(name.to_string()))
+            );
+        }
+        assert!(to_c(&program, "_f").is_ok());
+    }
+}
@@ -0,0 +1,196 @@
+use super::io::{ConsoleIo, NoIo};
+use super::{Opcode, Program, ProgramState, Termination};
+
+// a program lowered to pre-decoded ops for the hot loop: jump targets are resolved and range
+// checked once, and each step is a single match with no Result in between. it runs exactly like
+// Program::run_io, down to the visited set and step count in the final state, but has no tracer
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Nop,
+    Acc(i32),
+    // target in 0..=len
+    Jmp(usize),
+    // target outside the program, kept to report it
+    JmpOut(i64),
+    In(i32),
+    Out(i32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompiledProgram {
+    ops: Vec<Op>,
+}
+
+impl CompiledProgram {
+    pub fn new(program: &Program) -> Self {
+        let len = program.len() as i64;
+        let ops = program
+            .instructions()
+            .iter()
+            .enumerate()
+            .map(|(pc, instruction)| match instruction.op {
+                Opcode::Nop => Op::Nop,
+                Opcode::Acc => Op::Acc(instruction.value),
+                Opcode::Jmp => {
+                    let target = pc as i64 + instruction.value as i64;
+                    if target < 0 || target > len {
+                        Op::JmpOut(target)
+                    } else {
+                        Op::Jmp(target as usize)
+                    }
+                }
+                Opcode::In => Op::In(instruction.value),
+                Opcode::Out => Op::Out(instruction.value),
+            })
+            .collect();
+        CompiledProgram { ops }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    pub fn run(&self) -> Termination {
+        self.run_from(&ProgramState::new(), None)
+    }
+
+    pub fn run_from(
+        &self,
+        starting_program_state: &ProgramState,
+        max_steps: Option<usize>,
+    ) -> Termination {
+        self.run_io(starting_program_state, max_steps, &mut NoIo)
+    }
+
+    pub fn run_io(
+        &self,
+        starting_program_state: &ProgramState,
+        max_steps: Option<usize>,
+        io: &mut impl ConsoleIo,
+    ) -> Termination {
+        let mut state = starting_program_state.clone();
+        let len = self.ops.len();
+        let mut gas = max_steps.unwrap_or(usize::MAX);
+        loop {
+            let pc = state.current_instruction;
+            if pc == len {
+                return Termination::Halted(state);
+            }
+            if gas == 0 {
+                return Termination::StepLimitExceeded(state);
+            }
+            let op = match self.ops.get(pc) {
+                Some(&op) => op,
+                None => {
+                    return Termination::OutOfBounds {
+                        pc,
+                        target: pc as i64,
+                        state,
+                    }
+                }
+            };
+            if !matches!(op, Op::In(_)) && state.visited_instructions.contains(pc) {
+                return Termination::InfiniteLoop {
+                    pc,
+                    revisited_at_step: state.steps,
+                    state,
+                };
+            }
+
+            state.current_instruction = match op {
+                Op::Nop => pc + 1,
+                Op::Acc(value) => match state.accumulator.checked_add(value) {
+                    Some(accumulator) => {
+                        state.accumulator = accumulator;
+                        pc + 1
+                    }
+                    None => return Termination::InvalidInstruction { pc, state },
+                },
+                Op::Jmp(target) => target,
+                Op::JmpOut(target) => return Termination::OutOfBounds { pc, target, state },
+                Op::In(port) => match io.input(port) {
+                    Some(value) => {
+                        state.accumulator = value;
+                        state.visited_instructions.clear();
+                        pc + 1
+                    }
+                    None => return Termination::AwaitingInput { pc, state },
+                },
+                Op::Out(port) => {
+                    io.output(port, state.accumulator);
+                    pc + 1
+                }
+            };
+            state.visited_instructions.insert(pc);
+            state.steps += 1;
+            gas -= 1;
+        }
+    }
+}
+
+impl Program {
+    pub fn compile(&self) -> CompiledProgram {
+        CompiledProgram::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::io::QueueIo;
+    use super::super::test_support::{instruction, SAMPLE};
+    use super::*;
+    use proptest::prelude::*;
+
+    // Termination has no PartialEq (neither has ProgramState), Debug shows every field
+    fn same(a: &Termination, b: &Termination) -> bool {
+        format!("{:?}", a) == format!("{:?}", b)
+    }
+
+    #[test]
+    fn sample() {
+        let program = Program::parse(SAMPLE).unwrap();
+        let compiled = program.compile();
+        assert!(same(&compiled.run(), &program.run()));
+        for max_steps in 0..8 {
+            let start = ProgramState::new();
+            assert!(same(
+                &compiled.run_from(&start, Some(max_steps)),
+                &program.run_from(&start, Some(max_steps))
+            ));
+        }
+    }
+
+    #[test]
+    fn io() {
+        let program = Program::parse("in +0\nout +1\nacc +1\nout +2\njmp -4").unwrap();
+        let mut expected = QueueIo::with_input(vec![3, 4]);
+        let mut io = expected.clone();
+        assert!(same(
+            &program
+                .compile()
+                .run_io(&ProgramState::new(), None, &mut io),
+            &program.run_io(&ProgramState::new(), None, &mut expected)
+        ));
+        assert_eq!(io, expected);
+    }
+
+    proptest! {
+        #[test]
+        fn matches_interpreter(
+            instructions in prop::collection::vec(instruction(), 0..60),
+            max_steps in prop::option::of(0usize..30),
+        ) {
+            let program = Program::new(instructions);
+            let start = ProgramState::new();
+            prop_assert!(same(
+                &program.compile().run_from(&start, max_steps),
+                &program.run_from(&start, max_steps)
+            ));
+        }
+    }
+}
@@ -0,0 +1,519 @@
+use super::io::NoIo;
+use super::snapshot::Snapshot;
+use super::{Program, ProgramState, Termination};
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessEqual,
+    Equal,
+    NotEqual,
+    GreaterEqual,
+    Greater,
+}
+
+impl Comparison {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "<" => Some(Comparison::Less),
+            "<=" => Some(Comparison::LessEqual),
+            "==" => Some(Comparison::Equal),
+            "!=" => Some(Comparison::NotEqual),
+            ">=" => Some(Comparison::GreaterEqual),
+            ">" => Some(Comparison::Greater),
+            _ => None,
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            Comparison::Less => "<",
+            Comparison::LessEqual => "<=",
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::GreaterEqual => ">=",
+            Comparison::Greater => ">",
+        }
+    }
+}
+
+// fires when a step makes `acc <comparison> value` go from false to true
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub comparison: Comparison,
+    pub value: i32,
+}
+
+impl Watchpoint {
+    pub fn matches(&self, accumulator: i32) -> bool {
+        match self.comparison {
+            Comparison::Less => accumulator < self.value,
+            Comparison::LessEqual => accumulator <= self.value,
+            Comparison::Equal => accumulator == self.value,
+            Comparison::NotEqual => accumulator != self.value,
+            Comparison::GreaterEqual => accumulator >= self.value,
+            Comparison::Greater => accumulator > self.value,
+        }
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "acc {} {}", self.comparison.symbol(), self.value)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Stop {
+    // finished the requested number of steps
+    Stepped,
+    Breakpoint(usize),
+    // index into the watchpoint list
+    Watchpoint(usize),
+    // the next step would end the run, the state is left just before it
+    Terminated(Termination),
+    // nothing left in the history to reverse into
+    HistoryExhausted,
+}
+
+// what a reverse step needs, the visited set is rebuilt by removing pc again
+#[derive(Debug, Clone, Copy)]
+struct HistoryEntry {
+    pc: usize,
+    accumulator: i32,
+}
+
+pub const DEFAULT_HISTORY: usize = 4096;
+
+pub struct Debugger<'a> {
+    program: &'a Program,
+    state: ProgramState,
+    history: VecDeque<HistoryEntry>,
+    history_capacity: usize,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+impl<'a> Debugger<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        Debugger::with_history(program, DEFAULT_HISTORY)
+    }
+
+    // history_capacity bounds how many steps can be reversed, older steps are dropped
+    pub fn with_history(program: &'a Program, history_capacity: usize) -> Self {
+        Debugger {
+            program,
+            state: ProgramState::new(),
+            history: VecDeque::with_capacity(history_capacity.min(DEFAULT_HISTORY)),
+            history_capacity,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+        }
+    }
+
+    pub fn state(&self) -> &ProgramState {
+        &self.state
+    }
+
+    pub fn reset(&mut self) {
+        self.restore(ProgramState::new());
+    }
+
+    // continues from a state saved earlier, e.g. from a snapshot. reverse steps can't go back
+    // past it
+    pub fn restore(&mut self, state: ProgramState) {
+        self.state = state;
+        self.history.clear();
+    }
+
+    pub fn add_breakpoint(&mut self, pc: usize) -> bool {
+        self.breakpoints.insert(pc)
+    }
+
+    pub fn remove_breakpoint(&mut self, pc: usize) -> bool {
+        self.breakpoints.remove(&pc)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        self.watchpoints.push(watchpoint);
+        self.watchpoints.len() - 1
+    }
+
+    pub fn remove_watchpoint(&mut self, index: usize) -> Option<Watchpoint> {
+        if index < self.watchpoints.len() {
+            Some(self.watchpoints.remove(index))
+        } else {
+            None
+        }
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    // one instruction forward, keeping the state where it was if the run would end
+    fn step_once(&mut self) -> Result<(), Termination> {
+        let entry = HistoryEntry {
+            pc: self.state.current_instruction,
+            accumulator: self.state.accumulator,
+        };
+        let state = std::mem::take(&mut self.state);
+        match self.program.step(state, &mut NoIo) {
+            Ok(state) => {
+                self.state = state;
+                if self.history.len() == self.history_capacity {
+                    self.history.pop_front();
+                }
+                if self.history_capacity > 0 {
+                    self.history.push_back(entry);
+                }
+                Ok(())
+            }
+            Err(termination) => {
+                self.state = termination.state().clone();
+                Err(termination)
+            }
+        }
+    }
+
+    fn run_until(&mut self, max_steps: Option<usize>, check_stops: bool) -> Stop {
+        let mut steps = 0;
+        while max_steps.is_none_or(|max| steps < max) {
+            let before = self.state.accumulator;
+            if let Err(termination) = self.step_once() {
+                return Stop::Terminated(termination);
+            }
+            steps += 1;
+            if check_stops {
+                if self.breakpoints.contains(&self.state.current_instruction) {
+                    return Stop::Breakpoint(self.state.current_instruction);
+                }
+                let after = self.state.accumulator;
+                if let Some(index) = self
+                    .watchpoints
+                    .iter()
+                    .position(|w| !w.matches(before) && w.matches(after))
+                {
+                    return Stop::Watchpoint(index);
+                }
+            }
+        }
+        Stop::Stepped
+    }
+
+    // steps ignore breakpoints, so stepping off one works
+    pub fn step(&mut self, count: usize) -> Stop {
+        self.run_until(Some(count), false)
+    }
+
+    pub fn resume(&mut self) -> Stop {
+        self.run_until(None, true)
+    }
+
+    // runs until the program is about to repeat an instruction (or otherwise stops), ignoring
+    // breakpoints and watchpoints
+    pub fn run_to_loop(&mut self) -> Stop {
+        self.run_until(None, false)
+    }
+
+    pub fn reverse_step(&mut self, count: usize) -> Stop {
+        for _ in 0..count {
+            match self.history.pop_back() {
+                Some(entry) => {
+                    self.state.visited_instructions.remove(entry.pc);
+                    self.state.current_instruction = entry.pc;
+                    self.state.accumulator = entry.accumulator;
+                    self.state.steps -= 1;
+                }
+                None => return Stop::HistoryExhausted,
+            }
+        }
+        Stop::Stepped
+    }
+
+    // instructions within radius of pc, marking the current one with `=>` and breakpoints with `*`
+    pub fn listing(&self, radius: usize) -> String {
+        let pc = self.state.current_instruction;
+        let len = self.program.instructions.len();
+        let mut text = String::new();
+        let end = pc.saturating_add(radius).saturating_add(1).min(len + 1);
+        for line in pc.saturating_sub(radius)..end {
+            let marker = if line == pc { "=>" } else { "  " };
+            let breakpoint = if self.breakpoints.contains(&line) {
+                "*"
+            } else {
+                " "
+            };
+            match self.program.instructions.get(line) {
+                Some(instruction) => {
+                    text += &format!("{}{} {:>5}  {}\n", marker, breakpoint, line, instruction)
+                }
+                None => text += &format!("{}{} {:>5}  <end>\n", marker, breakpoint, line),
+            }
+        }
+        text
+    }
+
+    fn describe(&self, stop: &Stop) -> String {
+        match stop {
+            Stop::Stepped => String::new(),
+            Stop::Breakpoint(pc) => format!("breakpoint at {}\n", pc),
+            Stop::Watchpoint(index) => {
+                format!("watchpoint {} ({}) hit\n", index, self.watchpoints[*index])
+            }
+            Stop::Terminated(Termination::Halted(_)) => String::from("program halted\n"),
+            Stop::Terminated(Termination::InfiniteLoop {
+                pc,
+                revisited_at_step,
+                ..
+            }) => format!(
+                "infinite loop: instruction {} would run again at step {}\n",
+                pc, revisited_at_step
+            ),
+            Stop::Terminated(Termination::OutOfBounds { pc, target, .. }) => {
+                format!("instruction {} jumps out of bounds to {}\n", pc, target)
+            }
+            Stop::Terminated(Termination::StepLimitExceeded(_)) => {
+                String::from("step limit exceeded\n")
+            }
+            Stop::Terminated(Termination::InvalidInstruction { pc, .. }) => {
+                format!("instruction {} can't be executed\n", pc)
+            }
+            Stop::Terminated(Termination::AwaitingInput { pc, .. }) => {
+                format!("instruction {} is waiting for input\n", pc)
+            }
+            Stop::HistoryExhausted => String::from("no more history to reverse into\n"),
+        }
+    }
+
+    fn print_state(&self) -> String {
+        format!(
+            "pc {}  acc {}  steps {}\n",
+            self.state.current_instruction, self.state.accumulator, self.state.steps
+        )
+    }
+
+    // runs one command line and returns what to print, or None to quit
+    pub fn execute(&mut self, line: &str) -> Option<String> {
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => return Some(String::new()),
+        };
+        let args = words.collect::<Vec<&str>>();
+        let count = || {
+            args.first()
+                .and_then(|n| n.parse::<usize>().ok())
+                .unwrap_or(1)
+        };
+
+        let output = match command {
+            "s" | "step" => {
+                let stop = self.step(count());
+                self.describe(&stop) + &self.listing(0)
+            }
+            "rs" | "reverse-step" => {
+                let stop = self.reverse_step(count());
+                self.describe(&stop) + &self.listing(0)
+            }
+            "c" | "continue" => {
+                let stop = self.resume();
+                self.describe(&stop) + &self.listing(0)
+            }
+            "loop" | "run-to-loop" => {
+                let stop = self.run_to_loop();
+                self.describe(&stop) + &self.listing(0)
+            }
+            "b" | "break" => match args.first().and_then(|pc| pc.parse::<usize>().ok()) {
+                Some(pc) => {
+                    self.add_breakpoint(pc);
+                    format!("breakpoint at {}\n", pc)
+                }
+                None => String::from("usage: break <pc>\n"),
+            },
+            "d" | "delete" => match args.first().and_then(|pc| pc.parse::<usize>().ok()) {
+                Some(pc) if self.remove_breakpoint(pc) => format!("removed breakpoint at {}\n", pc),
+                _ => String::from("usage: delete <pc of an existing breakpoint>\n"),
+            },
+            "w" | "watch" => match args.as_slice() {
+                ["acc", comparison, value] => {
+                    match (Comparison::parse(comparison), value.parse::<i32>()) {
+                        (Some(comparison), Ok(value)) => {
+                            let watchpoint = Watchpoint { comparison, value };
+                            let index = self.add_watchpoint(watchpoint);
+                            format!("watchpoint {}: {}\n", index, watchpoint)
+                        }
+                        _ => String::from("usage: watch acc <|<=|==|!=|>=|> <value>\n"),
+                    }
+                }
+                _ => String::from("usage: watch acc <|<=|==|!=|>=|> <value>\n"),
+            },
+            "unwatch" => match args.first().and_then(|i| i.parse::<usize>().ok()) {
+                Some(index) if self.remove_watchpoint(index).is_some() => {
+                    format!("removed watchpoint {}\n", index)
+                }
+                _ => String::from("usage: unwatch <watchpoint number>\n"),
+            },
+            "p" | "print" => self.print_state(),
+            "l" | "list" => {
+                let radius = args.first().and_then(|n| n.parse::<usize>().ok());
+                self.listing(radius.unwrap_or(5))
+            }
+            "i" | "info" => {
+                let mut text = String::new();
+                for pc in self.breakpoints() {
+                    text += &format!("breakpoint at {}\n", pc);
+                }
+                for (index, watchpoint) in self.watchpoints.iter().enumerate() {
+                    text += &format!("watchpoint {}: {}\n", index, watchpoint);
+                }
+                text
+            }
+            "save" => match args.first() {
+                Some(path) => match Snapshot::new(self.program, &self.state).save(path) {
+                    Ok(()) => format!("saved to {}\n", path),
+                    Err(e) => format!("couldn't save to {}: {}\n", path, e),
+                },
+                None => String::from("usage: save <file>\n"),
+            },
+            "load" => match args.first().map(Snapshot::load) {
+                Some(Ok(snapshot)) if snapshot.program == *self.program => {
+                    self.restore(snapshot.state);
+                    self.listing(0)
+                }
+                Some(Ok(_)) => String::from("that snapshot is of a different program\n"),
+                Some(Err(e)) => format!("couldn't load: {}\n", e),
+                None => String::from("usage: load <file>\n"),
+            },
+            "r" | "reset" => {
+                self.reset();
+                self.listing(0)
+            }
+            "q" | "quit" => return None,
+            "h" | "help" => String::from(HELP),
+            _ => format!("unknown command {:?}, try help\n", command),
+        };
+        Some(output)
+    }
+
+    pub fn repl(&mut self, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        write!(output, "{}(dbg) ", self.listing(0))?;
+        output.flush()?;
+        for line in input.lines() {
+            match self.execute(&line?) {
+                Some(text) => write!(output, "{}(dbg) ", text)?,
+                None => break,
+            }
+            output.flush()?;
+        }
+        Ok(())
+    }
+}
+
+const HELP: &str = "\
+step [n]              run n instructions (s)
+reverse-step [n]      undo n instructions (rs)
+continue              run to a breakpoint, watchpoint or the end (c)
+run-to-loop           run until an instruction is about to repeat (loop)
+break <pc>            stop when execution reaches pc (b)
+delete <pc>           remove a breakpoint (d)
+watch acc <op> <n>    stop when the accumulator comparison becomes true (w)
+unwatch <n>           remove a watchpoint
+print                 show pc, accumulator and step count (p)
+list [radius]         show the instructions around pc (l)
+info                  show breakpoints and watchpoints (i)
+save <file>           write a snapshot of the run, json if the file ends in .json
+load <file>           continue from a snapshot of the same program
+reset                 start again from pc 0 (r)
+quit                  exit (q)
+";
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_support::SAMPLE;
+    use super::*;
+
+    #[test]
+    fn breakpoints_and_reverse() {
+        let program = Program::parse(SAMPLE).unwrap();
+        let mut debugger = Debugger::new(&program);
+        debugger.add_breakpoint(3);
+        assert!(matches!(debugger.resume(), Stop::Breakpoint(3)));
+        assert_eq!(debugger.state().accumulator, 2);
+
+        assert!(matches!(debugger.reverse_step(2), Stop::Stepped));
+        assert_eq!(debugger.state().current_instruction, 6);
+        assert_eq!(debugger.state().accumulator, 1);
+        assert!(!debugger.state().visited_instructions.contains(6));
+
+        match debugger.run_to_loop() {
+            Stop::Terminated(Termination::InfiniteLoop { pc, .. }) => assert_eq!(pc, 1),
+            other => panic!("unexpected stop {:?}", other),
+        }
+        assert_eq!(debugger.state().accumulator, 5);
+        assert!(matches!(debugger.reverse_step(100), Stop::HistoryExhausted));
+        assert_eq!(debugger.state().steps, 0);
+    }
+
+    #[test]
+    fn watchpoint() {
+        let program = Program::parse(SAMPLE).unwrap();
+        let mut debugger = Debugger::new(&program);
+        debugger.add_watchpoint(Watchpoint {
+            comparison: Comparison::Greater,
+            value: 2,
+        });
+        assert!(matches!(debugger.resume(), Stop::Watchpoint(0)));
+        assert_eq!(debugger.state().current_instruction, 4);
+        assert_eq!(debugger.state().accumulator, 5);
+    }
+
+    #[test]
+    fn repl() {
+        let program = Program::parse(SAMPLE).unwrap();
+        let mut debugger = Debugger::with_history(&program, 2);
+        let mut output = Vec::<u8>::new();
+        let input = "break 7\nc\np\nrs 3\nwatch acc > 4\ndelete 7\ncontinue\nq\nstep\n";
+        debugger.repl(input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("breakpoint at 7\n"));
+        assert!(output.contains("pc 7  acc 2  steps 4\n"));
+        assert!(output.contains("no more history"));
+        assert!(output.contains("watchpoint 0 (acc > 4) hit\n"));
+        assert!(output.contains("=>      4  jmp -3\n"));
+
+        // a radius past usize::MAX - pc lists up to the end
+        let listing = debugger.execute("list 18446744073709551615").unwrap();
+        assert!(listing.ends_with("        9  <end>\n"));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let program = Program::parse(SAMPLE).unwrap();
+        let path =
+            std::env::temp_dir().join(format!("console-debugger-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut debugger = Debugger::new(&program);
+        debugger.execute("step 4");
+        debugger.execute(&format!("save {}", path));
+        debugger.execute("reset");
+        debugger.execute(&format!("load {}", path));
+        assert_eq!(debugger.execute("p").unwrap(), "pc 7  acc 2  steps 4\n");
+
+        let other = Program::parse("nop +0").unwrap();
+        let mut debugger = Debugger::new(&other);
+        assert_eq!(
+            debugger.execute(&format!("load {}", path)).unwrap(),
+            "that snapshot is of a different program\n"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,562 @@
+use super::cfg::ControlFlowGraph;
+use super::{Opcode, Program};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ops::Range;
+
+// structured pseudo-code from the jmp graph. every instruction has exactly one successor, so
+// the structures are few: straight-line code with the jmps between blocks made implicit, one
+// `loop` per cycle (which never exits, there are no conditional jumps), and `if false` around
+// code a forward jmp skips over. code not reachable from pc 0 is printed in `unreachable`
+// sections, and a jump that can't be laid out in order becomes a `goto`.
+//
+// a loop is irreducible when it has more than one entry over the whole graph, counting pc 0 and
+// every jump into it from outside, dead code included. with one successor per instruction the
+// run enters a loop once at most, so a second entry always comes from dead code, which is where
+// a single nop/jmp swap would send the run
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Statement {
+    // start of the block at this pc, printed only where a goto needs it
+    Label(usize),
+    Acc {
+        pc: usize,
+        value: i32,
+    },
+    In {
+        pc: usize,
+        port: i32,
+    },
+    Out {
+        pc: usize,
+        port: i32,
+    },
+    // accumulator change over one pass, unless the body reads input
+    Loop {
+        accumulator_per_pass: Option<i64>,
+        body: Vec<Statement>,
+    },
+    // dead code the jmp at pc jumps over
+    IfFalse {
+        pc: usize,
+        body: Vec<Statement>,
+    },
+    Unreachable(Vec<Statement>),
+    Goto(usize),
+    Return,
+    Crash {
+        pc: usize,
+        target: i64,
+    },
+}
+
+// a loop and the instructions it can be entered at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IrreducibleLoop {
+    pub blocks: Vec<usize>,
+    pub entries: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decompiled {
+    pub statements: Vec<Statement>,
+    pub irreducible: Vec<IrreducibleLoop>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Next {
+    Block(usize),
+    Exit,
+    Crash { pc: usize, target: i64 },
+}
+
+struct Block {
+    start: usize,
+    end: usize,
+    next: Next,
+}
+
+struct Decompiler<'a> {
+    program: &'a Program,
+    blocks: Vec<Block>,
+    // index into cycles of the cycle a block is on
+    cycle: Vec<Option<usize>>,
+    cycles: Vec<Vec<usize>>,
+    reachable: Vec<bool>,
+    emitted: Vec<bool>,
+}
+
+impl<'a> Decompiler<'a> {
+    fn new(program: &'a Program) -> Self {
+        let cfg = ControlFlowGraph::new(program);
+        let instructions = program.instructions();
+        let len = instructions.len();
+
+        // blocks start at 0, at jmp targets and after jmps
+        let mut leaders = BTreeSet::<usize>::new();
+        leaders.insert(0);
+        for (pc, instruction) in instructions.iter().enumerate() {
+            if instruction.op == Opcode::Jmp {
+                leaders.insert(pc + 1);
+                if let Some(target) = cfg.successor(pc) {
+                    leaders.insert(target);
+                }
+            }
+        }
+        let starts = leaders
+            .into_iter()
+            .filter(|&pc| pc < len)
+            .collect::<Vec<usize>>();
+        let mut block_at = vec![0; len + 1];
+        for (index, &start) in starts.iter().enumerate() {
+            block_at[start] = index;
+        }
+        let blocks = starts
+            .iter()
+            .enumerate()
+            .map(|(index, &start)| {
+                let end = starts.get(index + 1).copied().unwrap_or(len);
+                let last = end - 1;
+                let next = match cfg.successor(last) {
+                    Some(target) if target == len => Next::Exit,
+                    Some(target) => Next::Block(block_at[target]),
+                    None => Next::Crash {
+                        pc: last,
+                        target: last as i64 + instructions[last].value as i64,
+                    },
+                };
+                Block { start, end, next }
+            })
+            .collect::<Vec<Block>>();
+
+        // the blocks on each cycle. only the first instruction of a block can be jumped to, so a
+        // cycle through any of a block's instructions runs through all of it
+        let mut cycle = vec![None; blocks.len()];
+        let mut cycles = Vec::<Vec<usize>>::new();
+        for pcs in cfg.cycles() {
+            let on = pcs
+                .iter()
+                .filter_map(|pc| starts.binary_search(pc).ok())
+                .collect::<Vec<usize>>();
+            for &b in &on {
+                cycle[b] = Some(cycles.len());
+            }
+            cycles.push(on);
+        }
+
+        let mut reachable = vec![false; blocks.len()];
+        let mut block = 0;
+        while block < blocks.len() && !reachable[block] {
+            reachable[block] = true;
+            match blocks[block].next {
+                Next::Block(next) => block = next,
+                _ => break,
+            }
+        }
+
+        let emitted = vec![false; blocks.len()];
+        Decompiler {
+            program,
+            blocks,
+            cycle,
+            cycles,
+            reachable,
+            emitted,
+        }
+    }
+
+    fn irreducible(&self) -> Vec<IrreducibleLoop> {
+        let mut entries = vec![BTreeSet::<usize>::new(); self.cycles.len()];
+        if let Some(Some(c)) = self.cycle.first() {
+            entries[*c].insert(0);
+        }
+        for (index, block) in self.blocks.iter().enumerate() {
+            if let Next::Block(next) = block.next {
+                if let Some(c) = self.cycle[next] {
+                    if self.cycle[index] != Some(c) {
+                        entries[c].insert(self.blocks[next].start);
+                    }
+                }
+            }
+        }
+        self.cycles
+            .iter()
+            .zip(entries)
+            .filter(|(_, entries)| entries.len() > 1)
+            .map(|(cycle, entries)| {
+                let mut blocks = cycle
+                    .iter()
+                    .map(|&b| self.blocks[b].start)
+                    .collect::<Vec<usize>>();
+                blocks.sort_unstable();
+                IrreducibleLoop {
+                    blocks,
+                    entries: entries.into_iter().collect(),
+                }
+            })
+            .collect()
+    }
+
+    // follows the flow from block until it ends or joins code already written out. within an
+    // `if false` the chain stays inside the skipped blocks
+    fn chain(&mut self, block: usize, within: Option<&Range<usize>>) -> Vec<Statement> {
+        let mut statements = Vec::<Statement>::new();
+        let mut next = Next::Block(block);
+        loop {
+            let block = match next {
+                Next::Exit => {
+                    statements.push(Statement::Return);
+                    break;
+                }
+                Next::Crash { pc, target } => {
+                    statements.push(Statement::Crash { pc, target });
+                    break;
+                }
+                Next::Block(block) => block,
+            };
+            let inside = |b: usize| within.is_none_or(|range| range.contains(&b));
+            let cycle = self.cycle[block].map(|c| self.cycles[c].clone());
+            let fits = match &cycle {
+                Some(cycle) => cycle.iter().all(|&b| inside(b)),
+                None => inside(block),
+            };
+            if self.emitted[block] || !fits {
+                statements.push(Statement::Goto(self.blocks[block].start));
+                break;
+            }
+            if cycle.is_some() {
+                let mut body = Vec::<Statement>::new();
+                let mut b = block;
+                loop {
+                    self.block(b, &mut body);
+                    b = match self.blocks[b].next {
+                        Next::Block(next) => next,
+                        _ => unreachable!(),
+                    };
+                    if b == block {
+                        break;
+                    }
+                }
+                statements.push(Statement::Loop {
+                    accumulator_per_pass: accumulator_per_pass(&body),
+                    body,
+                });
+                break;
+            }
+            self.block(block, &mut statements);
+            next = self.blocks[block].next;
+        }
+        statements
+    }
+
+    fn block(&mut self, index: usize, statements: &mut Vec<Statement>) {
+        self.emitted[index] = true;
+        let (start, end, next) = {
+            let block = &self.blocks[index];
+            (block.start, block.end, block.next)
+        };
+        statements.push(Statement::Label(start));
+        for pc in start..end {
+            let instruction = self.program.instructions()[pc];
+            match instruction.op {
+                Opcode::Acc => statements.push(Statement::Acc {
+                    pc,
+                    value: instruction.value,
+                }),
+                Opcode::In => statements.push(Statement::In {
+                    pc,
+                    port: instruction.value,
+                }),
+                Opcode::Out => statements.push(Statement::Out {
+                    pc,
+                    port: instruction.value,
+                }),
+                Opcode::Nop | Opcode::Jmp => {}
+            }
+        }
+
+        // a forward jmp over blocks that are all dead and not written out yet
+        let last = end - 1;
+        if self.program.instructions()[last].op != Opcode::Jmp {
+            return;
+        }
+        let skipped = match next {
+            Next::Block(target) if target > index + 1 => index + 1..target,
+            Next::Exit if index + 1 < self.blocks.len() => index + 1..self.blocks.len(),
+            _ => return,
+        };
+        if skipped
+            .clone()
+            .any(|b| self.reachable[b] || self.emitted[b])
+        {
+            return;
+        }
+        let mut body = Vec::<Statement>::new();
+        for b in skipped.clone() {
+            if !self.emitted[b] {
+                body.extend(self.chain(b, Some(&skipped)));
+            }
+        }
+        statements.push(Statement::IfFalse { pc: last, body });
+    }
+}
+
+fn accumulator_per_pass(body: &[Statement]) -> Option<i64> {
+    let mut sum = 0i64;
+    for statement in body {
+        match statement {
+            Statement::Acc { value, .. } => sum += *value as i64,
+            Statement::In { .. } => return None,
+            _ => {}
+        }
+    }
+    Some(sum)
+}
+
+pub fn decompile(program: &Program) -> Decompiled {
+    let mut decompiler = Decompiler::new(program);
+    let irreducible = decompiler.irreducible();
+    if decompiler.blocks.is_empty() {
+        return Decompiled {
+            statements: vec![Statement::Return],
+            irreducible,
+        };
+    }
+    let mut statements = decompiler.chain(0, None);
+
+    // dead code, starting with blocks nothing else leads to
+    let mut has_predecessor = vec![false; decompiler.blocks.len()];
+    for block in &decompiler.blocks {
+        if let Next::Block(next) = block.next {
+            has_predecessor[next] = true;
+        }
+    }
+    let order = (0..decompiler.blocks.len())
+        .filter(|&b| !has_predecessor[b])
+        .chain(0..decompiler.blocks.len())
+        .collect::<Vec<usize>>();
+    for block in order {
+        if !decompiler.emitted[block] {
+            statements.push(Statement::Unreachable(decompiler.chain(block, None)));
+        }
+    }
+
+    Decompiled {
+        statements,
+        irreducible,
+    }
+}
+
+impl Program {
+    pub fn decompile(&self) -> Decompiled {
+        decompile(self)
+    }
+}
+
+fn gotos(statements: &[Statement], targets: &mut BTreeSet<usize>) {
+    for statement in statements {
+        match statement {
+            Statement::Goto(target) => {
+                targets.insert(*target);
+            }
+            Statement::Loop { body, .. }
+            | Statement::IfFalse { body, .. }
+            | Statement::Unreachable(body) => gotos(body, targets),
+            _ => {}
+        }
+    }
+}
+
+fn write_statements(
+    f: &mut fmt::Formatter,
+    statements: &[Statement],
+    labels: &BTreeSet<usize>,
+    indent: usize,
+) -> fmt::Result {
+    let pad = " ".repeat(indent);
+    let line = |f: &mut fmt::Formatter, text: String, comment: String| {
+        if comment.is_empty() {
+            writeln!(f, "{}{}", pad, text)
+        } else {
+            writeln!(f, "{}{:<24}// {}", pad, text, comment)
+        }
+    };
+    for statement in statements {
+        match statement {
+            Statement::Label(pc) if labels.contains(pc) => writeln!(f, "{}L{}:", pad, pc)?,
+            Statement::Label(_) => {}
+            Statement::Acc { pc, value } if *value < 0 => {
+                line(f, format!("acc -= {}", -(*value as i64)), pc.to_string())?
+            }
+            Statement::Acc { pc, value } => line(f, format!("acc += {}", value), pc.to_string())?,
+            Statement::In { pc, port } => line(f, format!("acc = in({})", port), pc.to_string())?,
+            Statement::Out { pc, port } => line(f, format!("out({}, acc)", port), pc.to_string())?,
+            Statement::Loop {
+                accumulator_per_pass,
+                body,
+            } => {
+                let comment = match accumulator_per_pass {
+                    Some(sum) => format!("never exits, acc {:+} per pass", sum),
+                    None => String::from("never exits, reads input"),
+                };
+                line(f, String::from("loop {"), comment)?;
+                write_statements(f, body, labels, indent + 4)?;
+                writeln!(f, "{}}}", pad)?;
+            }
+            Statement::IfFalse { pc, body } => {
+                line(
+                    f,
+                    String::from("if false {"),
+                    format!("skipped by the jmp at {}", pc),
+                )?;
+                write_statements(f, body, labels, indent + 4)?;
+                writeln!(f, "{}}}", pad)?;
+            }
+            Statement::Unreachable(body) => {
+                writeln!(f, "{}unreachable {{", pad)?;
+                write_statements(f, body, labels, indent + 4)?;
+                writeln!(f, "{}}}", pad)?;
+            }
+            Statement::Goto(target) => writeln!(f, "{}goto L{}", pad, target)?,
+            Statement::Return => writeln!(f, "{}return acc", pad)?,
+            Statement::Crash { pc, target } => line(
+                f,
+                String::from("crash"),
+                format!("jmp at {} goes to {}", pc, target),
+            )?,
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for Decompiled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for irreducible in &self.irreducible {
+            let labels = |pcs: &[usize]| {
+                pcs.iter()
+                    .map(|pc| format!("L{}", pc))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            };
+            writeln!(
+                f,
+                "// irreducible: the loop through {} is entered at {}",
+                labels(&irreducible.blocks),
+                labels(&irreducible.entries)
+            )?;
+        }
+        let mut labels = BTreeSet::<usize>::new();
+        gotos(&self.statements, &mut labels);
+        write_statements(f, &self.statements, &labels, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_support::SAMPLE;
+    use super::*;
+
+    #[test]
+    fn sample() {
+        let decompiled = Program::parse(SAMPLE).unwrap().decompile();
+        // the run enters the loop at 1, the dead acc -99 at 6
+        assert_eq!(
+            decompiled.irreducible,
+            vec![IrreducibleLoop {
+                blocks: vec![1, 3, 6],
+                entries: vec![1, 6]
+            }]
+        );
+        assert_eq!(
+            decompiled.to_string(),
+            "\
+// irreducible: the loop through L1, L3, L6 is entered at L1, L6
+loop {                  // never exits, acc +5 per pass
+    acc += 1                // 1
+    L6:
+    acc += 1                // 6
+    acc += 3                // 3
+}
+unreachable {
+    acc -= 99               // 5
+    goto L6
+}
+unreachable {
+    acc += 6                // 8
+    return acc
+}
+"
+        );
+
+        // fixed, the loop opens up and the code it ran through is skipped over
+        let fixed = Program::parse(&SAMPLE.replace("jmp -4", "nop -4")).unwrap();
+        let decompiled = fixed.decompile();
+        assert!(decompiled.irreducible.is_empty());
+        assert_eq!(
+            decompiled.to_string(),
+            "\
+L1:
+acc += 1                // 1
+if false {              // skipped by the jmp at 2
+    acc += 3                // 3
+    goto L1
+    acc -= 99               // 5
+    goto L6
+}
+L6:
+acc += 1                // 6
+acc += 6                // 8
+return acc
+"
+        );
+    }
+
+    #[test]
+    fn skipped_code() {
+        let program = Program::parse("acc +1\njmp +3\nacc +2\nout +0\nacc -1\njmp +9").unwrap();
+        assert_eq!(
+            program.decompile().to_string(),
+            "\
+acc += 1                // 0
+if false {              // skipped by the jmp at 1
+    acc += 2                // 2
+    out(0, acc)             // 3
+    goto L4
+}
+L4:
+acc -= 1                // 4
+crash                   // jmp at 5 goes to 14
+"
+        );
+    }
+
+    #[test]
+    fn structure() {
+        let decompiled = Program::parse("acc +1\njmp -1").unwrap().decompile();
+        assert_eq!(decompiled.irreducible, vec![]);
+
+        assert_eq!(
+            decompiled.statements,
+            vec![Statement::Loop {
+                accumulator_per_pass: Some(1),
+                body: vec![Statement::Label(0), Statement::Acc { pc: 0, value: 1 }],
+            }]
+        );
+        assert_eq!(
+            Program::parse("").unwrap().decompile().statements,
+            vec![Statement::Return]
+        );
+
+        // a dead loop two dead jmps lead into at different places
+        let dead = Program::parse("jmp +5\njmp +2\njmp +2\nacc +1\njmp -1")
+            .unwrap()
+            .decompile();
+        assert_eq!(
+            dead.irreducible,
+            vec![IrreducibleLoop {
+                blocks: vec![3, 4],
+                entries: vec![3, 4]
+            }]
+        );
+    }
+}
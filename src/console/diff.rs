@@ -0,0 +1,252 @@
+use super::cfg::Repair;
+use super::trace::TraceStep;
+use super::{Program, ProgramState, Termination};
+use std::collections::BTreeSet;
+use std::fmt;
+
+// runs two variants of a program side by side, e.g. the original and the one fix_and_run
+// patches, and lines their traces up step by step to show where and how they part ways
+
+// the first step at which the runs differ. a run that has already stopped has no step there
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Divergence {
+    pub step: usize,
+    pub left: Option<TraceStep>,
+    pub right: Option<TraceStep>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TraceDiff {
+    pub left: Termination,
+    pub right: Termination,
+    // None when both runs executed exactly the same steps
+    pub divergence: Option<Divergence>,
+    // accumulator of each run after every step, for as long as either is running. a run that has
+    // stopped keeps its final accumulator
+    pub accumulators: Vec<(i32, i32)>,
+    // pcs executed by one run and never by the other
+    pub only_left: Vec<usize>,
+    pub only_right: Vec<usize>,
+}
+
+fn trace(program: &Program, max_steps: Option<usize>) -> (Termination, Vec<TraceStep>) {
+    let mut steps = Vec::<TraceStep>::new();
+    let termination = program.run_traced(&ProgramState::new(), max_steps, &mut steps);
+    (termination, steps)
+}
+
+// the same instruction at the same pc with the same effect
+fn same(a: &TraceStep, b: &TraceStep) -> bool {
+    (a.pc, a.op, a.value, a.accumulator_after) == (b.pc, b.op, b.value, b.accumulator_after)
+}
+
+impl TraceDiff {
+    pub fn new(left: &Program, right: &Program, max_steps: Option<usize>) -> Self {
+        let (left_termination, left_steps) = trace(left, max_steps);
+        let (right_termination, right_steps) = trace(right, max_steps);
+        let len = left_steps.len().max(right_steps.len());
+
+        let divergence = (0..len)
+            .find(
+                |&step| match (left_steps.get(step), right_steps.get(step)) {
+                    (Some(a), Some(b)) => !same(a, b),
+                    _ => true,
+                },
+            )
+            .map(|step| Divergence {
+                step,
+                left: left_steps.get(step).copied(),
+                right: right_steps.get(step).copied(),
+            });
+
+        let accumulator_at =
+            |steps: &[TraceStep], termination: &Termination, step: usize| match steps.get(step) {
+                Some(step) => step.accumulator_after,
+                None => termination.state().accumulator,
+            };
+        let accumulators = (0..len)
+            .map(|step| {
+                (
+                    accumulator_at(&left_steps, &left_termination, step),
+                    accumulator_at(&right_steps, &right_termination, step),
+                )
+            })
+            .collect();
+
+        let left_pcs = left_steps.iter().map(|s| s.pc).collect::<BTreeSet<usize>>();
+        let right_pcs = right_steps
+            .iter()
+            .map(|s| s.pc)
+            .collect::<BTreeSet<usize>>();
+
+        TraceDiff {
+            left: left_termination,
+            right: right_termination,
+            divergence,
+            accumulators,
+            only_left: left_pcs.difference(&right_pcs).copied().collect(),
+            only_right: right_pcs.difference(&left_pcs).copied().collect(),
+        }
+    }
+
+    // right minus left after every step
+    pub fn accumulator_deltas(&self) -> Vec<i64> {
+        self.accumulators
+            .iter()
+            .map(|&(left, right)| right as i64 - left as i64)
+            .collect()
+    }
+}
+
+impl Program {
+    // the diff between this program and the one fix_and_run would run, if there's a repair
+    pub fn diff_fix(&self) -> Option<(Repair, TraceDiff)> {
+        let repair = *self.repairs().first()?;
+        let mut fixed = self.clone();
+        repair.apply(&mut fixed);
+        Some((repair, TraceDiff::new(self, &fixed, None)))
+    }
+}
+
+fn outcome(termination: &Termination) -> String {
+    let state = termination.state();
+    let how = match termination {
+        Termination::Halted(_) => String::from("halts"),
+        Termination::InfiniteLoop { pc, .. } => format!("loops at {}", pc),
+        Termination::OutOfBounds { pc, target, .. } => {
+            format!("jumps out of bounds from {} to {}", pc, target)
+        }
+        Termination::StepLimitExceeded(_) => String::from("runs out of steps"),
+        Termination::InvalidInstruction { pc, .. } => format!("fails at {}", pc),
+        Termination::AwaitingInput { pc, .. } => format!("waits for input at {}", pc),
+    };
+    format!(
+        "{} after {} steps with acc {}",
+        how, state.steps, state.accumulator
+    )
+}
+
+fn describe(step: &Option<TraceStep>) -> String {
+    match step {
+        Some(step) => format!("{} `{} {:+}`", step.pc, step.op, step.value),
+        None => String::from("stopped"),
+    }
+}
+
+fn pcs(pcs: &[usize]) -> String {
+    if pcs.is_empty() {
+        String::from("none")
+    } else {
+        pcs.iter()
+            .map(|pc| pc.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+// a report along the lines of
+//
+//   left:  loops at 1 after 7 steps with acc 5
+//   right: halts after 6 steps with acc 8
+//   diverges at step 4: 7 `jmp -4` / 7 `nop -4`
+//   acc delta: +0 for steps 0-4, +3 for steps 5-6
+//   only left: 3, 4
+//   only right: 8
+impl fmt::Display for TraceDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "left:  {}", outcome(&self.left))?;
+        writeln!(f, "right: {}", outcome(&self.right))?;
+        match &self.divergence {
+            Some(divergence) => writeln!(
+                f,
+                "diverges at step {}: {} / {}",
+                divergence.step,
+                describe(&divergence.left),
+                describe(&divergence.right)
+            )?,
+            None => writeln!(f, "identical runs")?,
+        }
+
+        // runs of steps with the same delta
+        let deltas = self.accumulator_deltas();
+        let mut spans = Vec::<String>::new();
+        let mut start = 0;
+        for step in 0..deltas.len() {
+            if step + 1 == deltas.len() || deltas[step + 1] != deltas[step] {
+                let steps = if start == step {
+                    format!("step {}", step)
+                } else {
+                    format!("steps {}-{}", start, step)
+                };
+                spans.push(format!("{:+} for {}", deltas[step], steps));
+                start = step + 1;
+            }
+        }
+        if !spans.is_empty() {
+            writeln!(f, "acc delta: {}", spans.join(", "))?;
+        }
+        writeln!(f, "only left: {}", pcs(&self.only_left))?;
+        writeln!(f, "only right: {}", pcs(&self.only_right))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::generate::Generator;
+    use super::super::test_support::SAMPLE;
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn sample() {
+        let (repair, diff) = Program::parse(SAMPLE).unwrap().diff_fix().unwrap();
+        assert_eq!(repair.pc, 7);
+        let divergence = diff.divergence.unwrap();
+        assert_eq!(divergence.step, 4);
+        assert_eq!(diff.accumulator_deltas(), vec![0, 0, 0, 0, 0, 3, 3]);
+        assert_eq!(diff.only_left, vec![3, 4]);
+        assert_eq!(diff.only_right, vec![8]);
+        assert_eq!(
+            diff.to_string(),
+            "left:  loops at 1 after 7 steps with acc 5
+right: halts after 6 steps with acc 8
+diverges at step 4: 7 `jmp -4` / 7 `nop -4`
+acc delta: +0 for steps 0-4, +3 for steps 5-6
+only left: 3, 4
+only right: 8
+"
+        );
+    }
+
+    #[test]
+    fn identical() {
+        let program = Program::parse("acc +1\njmp +1").unwrap();
+        let diff = TraceDiff::new(&program, &program, None);
+        assert!(diff.divergence.is_none());
+        assert!(diff.only_left.is_empty() && diff.only_right.is_empty());
+
+        // a step limit applies to both runs alike
+        let diff = TraceDiff::new(&program, &program, Some(1));
+        assert!(diff.divergence.is_none());
+
+        // one run stopping early diverges where it stops
+        let shorter = Program::parse("acc +1").unwrap();
+        let diff = TraceDiff::new(&program, &shorter, None);
+        let divergence = diff.divergence.unwrap();
+        assert_eq!((divergence.step, divergence.right), (1, None));
+        assert_eq!(diff.accumulators, vec![(1, 1), (1, 1)]);
+    }
+
+    proptest! {
+        #[test]
+        fn generated(seed: u64, len in 2usize..100, accumulator: i32) {
+            let program = Generator::new(seed).looping(len, accumulator).0;
+            let (repair, diff) = program.diff_fix().unwrap();
+            // both runs agree until the repaired instruction
+            let divergence = diff.divergence.unwrap();
+            prop_assert_eq!(divergence.left.unwrap().pc, repair.pc);
+            prop_assert_eq!(divergence.right.unwrap().pc, repair.pc);
+            prop_assert_eq!(diff.accumulators.last().unwrap().1, accumulator);
+        }
+    }
+}
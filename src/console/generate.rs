@@ -0,0 +1,273 @@
+use super::cfg::Repair;
+use super::{Instruction, Opcode, Program};
+use std::ops::Range;
+
+// seeded generator for test programs with a known answer. the same seed always gives the same
+// programs, the random numbers come from splitmix64 here rather than a crate so that stays true.
+//
+// a program is laid out as a random walk over a random subset of its instructions (the path),
+// connected by fallthrough where the next instruction happens to follow and jmps elsewhere, with
+// the rest filled with dead code. the last instruction on the path is an acc that makes the
+// accumulator come out at the requested value
+
+// largest accumulator change of a generated acc, apart from the final one
+const MAX_ACC: i64 = 50;
+
+pub struct Generator {
+    state: u64,
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Self {
+        Generator { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // uniform in 0..n, n > 0
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    fn between(&mut self, range: Range<i64>) -> i64 {
+        range.start + (self.next_u64() % (range.end - range.start) as u64) as i64
+    }
+
+    fn chance(&mut self, percent: u64) -> bool {
+        self.next_u64() % 100 < percent
+    }
+
+    // accs lean towards the sign of a large target so the final acc can make up the rest
+    // without overflowing
+    fn acc_value(&mut self, target: i32) -> i32 {
+        let value = self.between(-MAX_ACC..MAX_ACC + 1);
+        if target > i32::MAX / 2 {
+            value.abs() as i32
+        } else if target < i32::MIN / 2 {
+            -value.abs() as i32
+        } else {
+            value as i32
+        }
+    }
+
+    // the order the run visits the region in: its first instruction, a shuffled random subset of
+    // the middle, then its last instruction
+    fn path(&mut self, region: Range<usize>) -> Vec<usize> {
+        let last = region.end - 1;
+        let mut middle = (region.start + 1..last).collect::<Vec<usize>>();
+        for i in (1..middle.len()).rev() {
+            let j = self.below(i + 1);
+            middle.swap(i, j);
+        }
+        middle.truncate(self.below(middle.len() + 1));
+
+        let mut path = vec![region.start];
+        path.extend(middle);
+        if last != region.start {
+            path.push(last);
+        }
+        path
+    }
+
+    // connects the path, leaving from its last instruction to exit. a nop's argument points into
+    // nop_targets, so that swapping it for a jmp stays there
+    fn lay_out(
+        &mut self,
+        cells: &mut [Option<Instruction>],
+        path: &[usize],
+        exit: usize,
+        nop_targets: Range<usize>,
+        target: i32,
+    ) {
+        for (i, &pc) in path.iter().enumerate() {
+            let next = path.get(i + 1).copied().unwrap_or(exit);
+            let instruction = if next == pc + 1 && !self.chance(10) {
+                if self.chance(30) {
+                    let to = nop_targets.start + self.below(nop_targets.len());
+                    Instruction {
+                        op: Opcode::Nop,
+                        value: (to as i64 - pc as i64) as i32,
+                    }
+                } else {
+                    Instruction {
+                        op: Opcode::Acc,
+                        value: self.acc_value(target),
+                    }
+                }
+            } else {
+                Instruction {
+                    op: Opcode::Jmp,
+                    value: (next as i64 - pc as i64) as i32,
+                }
+            };
+            cells[pc] = Some(instruction);
+        }
+    }
+
+    // dead code for whatever is left of the region. jmps land in targets, and fallthrough is only
+    // used where the next instruction is in targets too
+    fn fill(
+        &mut self,
+        cells: &mut [Option<Instruction>],
+        region: Range<usize>,
+        targets: Range<usize>,
+    ) {
+        for pc in region {
+            if cells[pc].is_some() {
+                continue;
+            }
+            let op = if targets.contains(&(pc + 1)) {
+                [Opcode::Nop, Opcode::Acc, Opcode::Jmp][self.below(3)]
+            } else {
+                Opcode::Jmp
+            };
+            let value = match op {
+                Opcode::Acc => self.between(-MAX_ACC..MAX_ACC + 1),
+                _ => (targets.start + self.below(targets.len())) as i64 - pc as i64,
+            };
+            cells[pc] = Some(Instruction {
+                op,
+                value: value as i32,
+            });
+        }
+    }
+
+    // makes the accumulator at the end of the path come out at target with the last instruction
+    fn settle(cells: &mut [Option<Instruction>], path: &[usize], target: i32) {
+        let (&last, rest) = path.split_last().unwrap();
+        let sum = rest
+            .iter()
+            .filter_map(|&pc| cells[pc])
+            .filter(|instruction| instruction.op == Opcode::Acc)
+            .map(|instruction| instruction.value as i64)
+            .sum::<i64>();
+        cells[last] = Some(Instruction {
+            op: Opcode::Acc,
+            value: (target as i64 - sum) as i32,
+        });
+    }
+
+    // a program of len instructions that halts with the given accumulator. an empty program can
+    // only halt with 0
+    pub fn terminating(&mut self, len: usize, accumulator: i32) -> Program {
+        assert!(
+            len > 0 || accumulator == 0,
+            "an empty program halts with accumulator 0"
+        );
+        if len == 0 {
+            return Program::new(Vec::new());
+        }
+        let mut cells = vec![None; len];
+        let path = self.path(0..len);
+        self.lay_out(&mut cells, &path, len, 0..len, accumulator);
+        Generator::settle(&mut cells, &path, accumulator);
+        self.fill(&mut cells, 0..len, 0..len);
+        Program::new(cells.into_iter().map(Option::unwrap).collect())
+    }
+
+    // a program of len instructions (at least 2) that loops, along with the one nop/jmp swap that
+    // makes it halt, which it does with the given accumulator.
+    //
+    // the program splits at a random point. everything before it, dead code included, only ever
+    // jumps or falls through to somewhere before it, and the instruction just before the split is
+    // the jmp closing the loop. so no other swap can get out, and swapping that jmp for a nop
+    // falls through into a terminating program after the split
+    pub fn looping(&mut self, len: usize, accumulator: i32) -> (Program, Repair) {
+        assert!(
+            len >= 2,
+            "a looping program with a fix needs 2 instructions"
+        );
+        let split = 1 + self.below(len - 1);
+        let mut cells = vec![None; len];
+
+        let head = self.path(0..split);
+        self.lay_out(&mut cells, &head, split, 0..split, accumulator);
+        let back_to = head[self.below(head.len())];
+        cells[split - 1] = Some(Instruction {
+            op: Opcode::Jmp,
+            value: back_to as i32 - (split - 1) as i32,
+        });
+
+        let tail = self.path(split..len);
+        self.lay_out(&mut cells, &tail, len, 0..len, accumulator);
+        let fixed_path = head.iter().chain(&tail).copied().collect::<Vec<usize>>();
+        Generator::settle(&mut cells, &fixed_path, accumulator);
+
+        self.fill(&mut cells, 0..split, 0..split);
+        self.fill(&mut cells, split..len, 0..len);
+        let program = Program::new(cells.into_iter().map(Option::unwrap).collect());
+        let repair = Repair {
+            pc: split - 1,
+            from: Opcode::Jmp,
+            to: Opcode::Nop,
+            accumulator,
+        };
+        (program, repair)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::mutation::SearchOptions;
+    use super::super::Termination;
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn reproducible() {
+        let a = Generator::new(7).looping(50, 3);
+        let b = Generator::new(7).looping(50, 3);
+        assert_eq!(a, b);
+        assert_ne!(a.0, Generator::new(8).looping(50, 3).0);
+    }
+
+    proptest! {
+        #[test]
+        fn terminating(seed: u64, len in 1usize..300, accumulator: i32) {
+            let program = Generator::new(seed).terminating(len, accumulator);
+            prop_assert_eq!(program.len(), len);
+            match program.run() {
+                Termination::Halted(state) => prop_assert_eq!(state.accumulator, accumulator),
+                other => prop_assert!(false, "{:?}", other),
+            }
+            prop_assert!(program.repairs().is_empty());
+            prop_assert!(program.compile().run().is_halted());
+        }
+
+        #[test]
+        fn looping(seed: u64, len in 2usize..300, accumulator: i32) {
+            let (program, repair) = Generator::new(seed).looping(len, accumulator);
+            prop_assert_eq!(program.len(), len);
+            let looped = matches!(program.run(), Termination::InfiniteLoop { .. });
+            prop_assert!(looped);
+            prop_assert_eq!(program.repairs(), vec![repair]);
+            match program.fix_and_run() {
+                Termination::Halted(state) => prop_assert_eq!(state.accumulator, accumulator),
+                other => prop_assert!(false, "{:?}", other),
+            }
+        }
+
+        // the search may also turn an acc into a jmp, so it isn't bound to find the same fix
+        #[test]
+        fn mutation_search(seed: u64, len in 2usize..60, accumulator in -1000i32..1000) {
+            let (program, _) = Generator::new(seed).looping(len, accumulator);
+            let options = SearchOptions {
+                max_edits: 1,
+                ..SearchOptions::default()
+            };
+            let solution = program.search_mutations(&options).unwrap();
+            match solution.program.run() {
+                Termination::Halted(state) => {
+                    prop_assert_eq!(state.accumulator, solution.state.accumulator)
+                }
+                other => prop_assert!(false, "{:?}", other),
+            }
+        }
+    }
+}
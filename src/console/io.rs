@@ -0,0 +1,223 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+
+// what `in +N` and `out +N` talk to. the argument of the instruction is the port
+pub trait ConsoleIo {
+    // None when nothing is available yet, which pauses the run with Termination::AwaitingInput
+    fn input(&mut self, port: i32) -> Option<i32>;
+    fn output(&mut self, port: i32, value: i32);
+}
+
+impl<T: ConsoleIo + ?Sized> ConsoleIo for &mut T {
+    fn input(&mut self, port: i32) -> Option<i32> {
+        (**self).input(port)
+    }
+
+    fn output(&mut self, port: i32, value: i32) {
+        (**self).output(port, value)
+    }
+}
+
+// never has input and drops output, what run_from uses
+pub struct NoIo;
+
+impl ConsoleIo for NoIo {
+    fn input(&mut self, _: i32) -> Option<i32> {
+        None
+    }
+
+    fn output(&mut self, _: i32, _: i32) {}
+}
+
+// reads from one queue whatever the port, and keeps every (port, value) written
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueueIo {
+    pub input: VecDeque<i32>,
+    pub output: Vec<(i32, i32)>,
+}
+
+impl QueueIo {
+    pub fn new() -> Self {
+        QueueIo::default()
+    }
+
+    pub fn with_input(input: impl IntoIterator<Item = i32>) -> Self {
+        QueueIo {
+            input: input.into_iter().collect(),
+            output: Vec::new(),
+        }
+    }
+
+    pub fn push_input(&mut self, value: i32) {
+        self.input.push_back(value);
+    }
+
+    // output values, dropping the ports
+    pub fn output_values(&self) -> Vec<i32> {
+        self.output.iter().map(|&(_, value)| value).collect()
+    }
+}
+
+impl ConsoleIo for QueueIo {
+    fn input(&mut self, _: i32) -> Option<i32> {
+        self.input.pop_front()
+    }
+
+    fn output(&mut self, port: i32, value: i32) {
+        self.output.push((port, value));
+    }
+}
+
+pub struct FnIo<I, O> {
+    input: I,
+    output: O,
+}
+
+impl<I, O> FnIo<I, O>
+where
+    I: FnMut(i32) -> Option<i32>,
+    O: FnMut(i32, i32),
+{
+    pub fn new(input: I, output: O) -> Self {
+        FnIo { input, output }
+    }
+}
+
+impl<I, O> ConsoleIo for FnIo<I, O>
+where
+    I: FnMut(i32) -> Option<i32>,
+    O: FnMut(i32, i32),
+{
+    fn input(&mut self, port: i32) -> Option<i32> {
+        (self.input)(port)
+    }
+
+    fn output(&mut self, port: i32, value: i32) {
+        (self.output)(port, value)
+    }
+}
+
+// reads lines until one is a number, skipping any that aren't. None at the end of input
+fn read_number(mut read_line: impl FnMut(&mut String) -> io::Result<usize>) -> Option<i32> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match read_line(&mut line) {
+            Ok(0) | Err(_) => return None,
+            Ok(_) => {
+                if let Ok(value) = line.trim().parse::<i32>() {
+                    return Some(value);
+                }
+            }
+        }
+    }
+}
+
+// one integer per line on stdin and stdout, ports are ignored. input blocks on the terminal as
+// usual and end of input leaves the run awaiting input. stdin is only locked while a line is
+// read, so the rest of the process can still use it between `in`s
+#[derive(Debug, Default)]
+pub struct StdIo;
+
+impl StdIo {
+    pub fn new() -> Self {
+        StdIo
+    }
+}
+
+impl ConsoleIo for StdIo {
+    fn input(&mut self, _: i32) -> Option<i32> {
+        read_number(|line| io::stdin().read_line(line))
+    }
+
+    fn output(&mut self, _: i32, value: i32) {
+        // the vm can't fail on io, a closed stdout just drops output
+        let _ = writeln!(io::stdout(), "{}", value);
+    }
+}
+
+// StdIo over any pair of streams
+pub struct StreamIo<R: BufRead, W: Write> {
+    reader: R,
+    writer: W,
+}
+
+impl<R: BufRead, W: Write> StreamIo<R, W> {
+    pub fn new(reader: R, writer: W) -> Self {
+        StreamIo { reader, writer }
+    }
+}
+
+impl<R: BufRead, W: Write> ConsoleIo for StreamIo<R, W> {
+    fn input(&mut self, _: i32) -> Option<i32> {
+        read_number(|line| self.reader.read_line(line))
+    }
+
+    fn output(&mut self, _: i32, value: i32) {
+        let _ = writeln!(self.writer, "{}", value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Program, ProgramState, Termination};
+    use super::*;
+
+    // copies every input to ports 1 and 2
+    fn echo() -> Program {
+        Program::parse("in +0\nout +1\nout +2\njmp -3").unwrap()
+    }
+
+    #[test]
+    fn blocking_and_resume() {
+        let program = echo();
+        let mut io = QueueIo::with_input(vec![3, 4]);
+        let termination = program.run_io(&ProgramState::new(), None, &mut io);
+        let state = match termination {
+            Termination::AwaitingInput { pc, state } => {
+                assert_eq!(pc, 0);
+                state
+            }
+            other => panic!("unexpected termination {:?}", other),
+        };
+        assert_eq!(io.output, vec![(1, 3), (2, 3), (1, 4), (2, 4)]);
+
+        io.push_input(5);
+        let termination = program.run_io(&state, None, &mut io);
+        assert!(matches!(termination, Termination::AwaitingInput { .. }));
+        assert_eq!(io.output_values()[4..], [5, 5]);
+    }
+
+    #[test]
+    fn closures_and_streams() {
+        let program = Program::parse("in +7\nacc +1\nout +9").unwrap();
+        let mut written = Vec::<(i32, i32)>::new();
+        let mut io = FnIo::new(
+            |port| Some(port * 10),
+            |port, value| written.push((port, value)),
+        );
+        assert!(program
+            .run_io(&ProgramState::new(), None, &mut io)
+            .is_halted());
+        assert_eq!(written, vec![(9, 71)]);
+
+        let mut output = Vec::<u8>::new();
+        let mut io = StreamIo::new("41\n".as_bytes(), &mut output);
+        program.run_io(&ProgramState::new(), None, &mut io);
+        assert_eq!(output, b"42\n");
+
+        // lines that aren't numbers are skipped over, not read as nothing
+        let mut output = Vec::<u8>::new();
+        let mut io = StreamIo::new("x\n\n 6 \n".as_bytes(), &mut output);
+        assert!(program
+            .run_io(&ProgramState::new(), None, &mut io)
+            .is_halted());
+        assert_eq!(output, b"7\n");
+
+        // no io at all, the in never gets a value
+        assert!(matches!(
+            program.run(),
+            Termination::AwaitingInput { pc: 0, .. }
+        ));
+    }
+}
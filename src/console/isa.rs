@@ -0,0 +1,787 @@
+use super::{tokens, ParseError, ParseErrorKind, Program, Termination, VisitedSet};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+// an instruction set for a register machine. Isa::default() is the day8 console (one `acc`
+// register, nop/acc/jmp, stop on the first revisited instruction) and Isa::extended() adds
+// registers, arithmetic, conditional jumps, calls and hlt. more instructions can be added by
+// implementing Operation and registering it
+
+pub const MAX_CALL_DEPTH: usize = 1 << 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandKind {
+    Register,
+    // a register or an immediate
+    Value,
+    Immediate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operand {
+    Register(usize),
+    Immediate(i32),
+}
+
+// where execution goes after an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Next,
+    // relative to the current instruction
+    Jump(i64),
+    Goto(usize),
+    Halt,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    Overflow,
+    StackUnderflow,
+    StackOverflow,
+    Custom(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MachineState {
+    pub registers: Vec<i32>,
+    pub pc: usize,
+    pub stack: Vec<usize>,
+    pub steps: usize,
+    // why the last instruction couldn't run, set with Termination::InvalidInstruction
+    pub fault: Option<Fault>,
+}
+
+impl MachineState {
+    pub fn new(registers: usize) -> Self {
+        MachineState {
+            registers: vec![0; registers],
+            ..MachineState::default()
+        }
+    }
+
+    pub fn value(&self, operand: Operand) -> i32 {
+        match operand {
+            Operand::Register(register) => self.registers[register],
+            Operand::Immediate(value) => value,
+        }
+    }
+
+    // the register an operand of kind Register names
+    pub fn register(&mut self, operand: Operand) -> &mut i32 {
+        match operand {
+            Operand::Register(register) => &mut self.registers[register],
+            Operand::Immediate(_) => panic!("operand is not a register"),
+        }
+    }
+}
+
+pub trait Operation: Send + Sync {
+    fn mnemonic(&self) -> &str;
+    fn operands(&self) -> &[OperandKind];
+    // operands have already been checked against operands(). registers and the top of the stack
+    // can change, and are put back if this faults or the flow leaves the program
+    fn execute(&self, operands: &[Operand], state: &mut MachineState) -> Result<Flow, Fault>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopDetection {
+    // day8 rule: any instruction running twice is a loop
+    RevisitedInstruction,
+    // pc, registers and stack all repeating is a loop, exact for any deterministic program
+    RepeatedState,
+    // rely on the step limit
+    None,
+}
+
+// what a step can change, saved so a fault or bad jump leaves the state as it was. operations
+// only push or pop the top of the stack, so its depth and top entry are enough to put it back
+// without copying the whole stack every step
+struct Checkpoint {
+    registers: Vec<i32>,
+    depth: usize,
+    top: Option<usize>,
+}
+
+impl Checkpoint {
+    fn new(state: &MachineState) -> Self {
+        Checkpoint {
+            registers: state.registers.clone(),
+            depth: state.stack.len(),
+            top: state.stack.last().copied(),
+        }
+    }
+
+    fn restore(self, state: &mut MachineState) {
+        state.registers = self.registers;
+        state.stack.truncate(self.depth);
+        if let Some(top) = self.top {
+            if state.stack.len() < self.depth {
+                state.stack.push(top);
+            } else {
+                state.stack[self.depth - 1] = top;
+            }
+        }
+    }
+}
+
+// brent's cycle detection: compare each state to one saved at the last power of two steps.
+// a repeat is found within two cycle lengths of entering the cycle, and only ever one state is
+// kept, where recording every state costs a copy of the stack per step
+struct Brent {
+    saved: Option<(usize, Vec<i32>, Vec<usize>)>,
+    power: usize,
+    since: usize,
+}
+
+impl Brent {
+    fn new() -> Self {
+        Brent {
+            saved: None,
+            power: 1,
+            since: 0,
+        }
+    }
+
+    // whether state repeats the saved one
+    fn repeated(&mut self, state: &MachineState) -> bool {
+        if let Some((pc, registers, stack)) = &self.saved {
+            // cheapest differences first, the stack is only walked when its depth matches
+            if *pc == state.pc
+                && stack.len() == state.stack.len()
+                && *registers == state.registers
+                && *stack == state.stack
+            {
+                return true;
+            }
+        }
+        if self.saved.is_none() || self.since == self.power {
+            self.saved = Some((state.pc, state.registers.clone(), state.stack.clone()));
+            if self.since == self.power {
+                self.power *= 2;
+            }
+            self.since = 0;
+        }
+        self.since += 1;
+        false
+    }
+}
+
+struct Nop;
+struct Acc;
+struct Jmp;
+struct Mov;
+struct Add;
+struct Mul;
+struct Jz;
+struct Jnz;
+struct Call;
+struct Ret;
+struct Hlt;
+
+fn checked(value: Option<i32>) -> Result<i32, Fault> {
+    value.ok_or(Fault::Overflow)
+}
+
+impl Operation for Nop {
+    fn mnemonic(&self) -> &str {
+        "nop"
+    }
+    fn operands(&self) -> &[OperandKind] {
+        &[OperandKind::Value]
+    }
+    fn execute(&self, _: &[Operand], _: &mut MachineState) -> Result<Flow, Fault> {
+        Ok(Flow::Next)
+    }
+}
+
+impl Operation for Acc {
+    fn mnemonic(&self) -> &str {
+        "acc"
+    }
+    fn operands(&self) -> &[OperandKind] {
+        &[OperandKind::Value]
+    }
+    fn execute(&self, operands: &[Operand], state: &mut MachineState) -> Result<Flow, Fault> {
+        let value = state.value(operands[0]);
+        state.registers[0] = checked(state.registers[0].checked_add(value))?;
+        Ok(Flow::Next)
+    }
+}
+
+impl Operation for Jmp {
+    fn mnemonic(&self) -> &str {
+        "jmp"
+    }
+    fn operands(&self) -> &[OperandKind] {
+        &[OperandKind::Value]
+    }
+    fn execute(&self, operands: &[Operand], state: &mut MachineState) -> Result<Flow, Fault> {
+        Ok(Flow::Jump(state.value(operands[0]) as i64))
+    }
+}
+
+impl Operation for Mov {
+    fn mnemonic(&self) -> &str {
+        "mov"
+    }
+    fn operands(&self) -> &[OperandKind] {
+        &[OperandKind::Register, OperandKind::Value]
+    }
+    fn execute(&self, operands: &[Operand], state: &mut MachineState) -> Result<Flow, Fault> {
+        let value = state.value(operands[1]);
+        *state.register(operands[0]) = value;
+        Ok(Flow::Next)
+    }
+}
+
+impl Operation for Add {
+    fn mnemonic(&self) -> &str {
+        "add"
+    }
+    fn operands(&self) -> &[OperandKind] {
+        &[OperandKind::Register, OperandKind::Value]
+    }
+    fn execute(&self, operands: &[Operand], state: &mut MachineState) -> Result<Flow, Fault> {
+        let value = state.value(operands[1]);
+        let register = state.register(operands[0]);
+        *register = checked(register.checked_add(value))?;
+        Ok(Flow::Next)
+    }
+}
+
+impl Operation for Mul {
+    fn mnemonic(&self) -> &str {
+        "mul"
+    }
+    fn operands(&self) -> &[OperandKind] {
+        &[OperandKind::Register, OperandKind::Value]
+    }
+    fn execute(&self, operands: &[Operand], state: &mut MachineState) -> Result<Flow, Fault> {
+        let value = state.value(operands[1]);
+        let register = state.register(operands[0]);
+        *register = checked(register.checked_mul(value))?;
+        Ok(Flow::Next)
+    }
+}
+
+impl Operation for Jz {
+    fn mnemonic(&self) -> &str {
+        "jz"
+    }
+    fn operands(&self) -> &[OperandKind] {
+        &[OperandKind::Value, OperandKind::Value]
+    }
+    fn execute(&self, operands: &[Operand], state: &mut MachineState) -> Result<Flow, Fault> {
+        if state.value(operands[0]) == 0 {
+            Ok(Flow::Jump(state.value(operands[1]) as i64))
+        } else {
+            Ok(Flow::Next)
+        }
+    }
+}
+
+impl Operation for Jnz {
+    fn mnemonic(&self) -> &str {
+        "jnz"
+    }
+    fn operands(&self) -> &[OperandKind] {
+        &[OperandKind::Value, OperandKind::Value]
+    }
+    fn execute(&self, operands: &[Operand], state: &mut MachineState) -> Result<Flow, Fault> {
+        if state.value(operands[0]) != 0 {
+            Ok(Flow::Jump(state.value(operands[1]) as i64))
+        } else {
+            Ok(Flow::Next)
+        }
+    }
+}
+
+impl Operation for Call {
+    fn mnemonic(&self) -> &str {
+        "call"
+    }
+    fn operands(&self) -> &[OperandKind] {
+        &[OperandKind::Value]
+    }
+    fn execute(&self, operands: &[Operand], state: &mut MachineState) -> Result<Flow, Fault> {
+        if state.stack.len() == MAX_CALL_DEPTH {
+            return Err(Fault::StackOverflow);
+        }
+        state.stack.push(state.pc + 1);
+        Ok(Flow::Jump(state.value(operands[0]) as i64))
+    }
+}
+
+impl Operation for Ret {
+    fn mnemonic(&self) -> &str {
+        "ret"
+    }
+    fn operands(&self) -> &[OperandKind] {
+        &[]
+    }
+    fn execute(&self, _: &[Operand], state: &mut MachineState) -> Result<Flow, Fault> {
+        state
+            .stack
+            .pop()
+            .map(Flow::Goto)
+            .ok_or(Fault::StackUnderflow)
+    }
+}
+
+impl Operation for Hlt {
+    fn mnemonic(&self) -> &str {
+        "hlt"
+    }
+    fn operands(&self) -> &[OperandKind] {
+        &[]
+    }
+    fn execute(&self, _: &[Operand], _: &mut MachineState) -> Result<Flow, Fault> {
+        Ok(Flow::Halt)
+    }
+}
+
+#[derive(Clone)]
+pub struct Isa {
+    registers: Vec<String>,
+    operations: HashMap<String, Arc<dyn Operation>>,
+    loop_detection: LoopDetection,
+}
+
+// the day8 console
+impl Default for Isa {
+    fn default() -> Self {
+        let mut isa = Isa {
+            registers: vec![String::from("acc")],
+            operations: HashMap::new(),
+            loop_detection: LoopDetection::RevisitedInstruction,
+        };
+        isa.register(Nop).register(Acc).register(Jmp);
+        isa
+    }
+}
+
+impl Isa {
+    // registers acc, a, b, c and d, plus mov/add/mul/jz/jnz/call/ret/hlt
+    pub fn extended() -> Self {
+        let mut isa = Isa::default();
+        isa.registers
+            .extend(["a", "b", "c", "d"].iter().map(|r| String::from(*r)));
+        isa.loop_detection = LoopDetection::RepeatedState;
+        isa.register(Mov)
+            .register(Add)
+            .register(Mul)
+            .register(Jz)
+            .register(Jnz)
+            .register(Call)
+            .register(Ret)
+            .register(Hlt);
+        isa
+    }
+
+    // adds or replaces an instruction
+    pub fn register(&mut self, operation: impl Operation + 'static) -> &mut Self {
+        self.operations
+            .insert(String::from(operation.mnemonic()), Arc::new(operation));
+        self
+    }
+
+    // register 0 is always the accumulator, named acc
+    pub fn set_registers(&mut self, names: &[&str]) -> &mut Self {
+        self.registers = std::iter::once("acc")
+            .chain(names.iter().copied().filter(|&name| name != "acc"))
+            .map(String::from)
+            .collect();
+        self
+    }
+
+    pub fn set_loop_detection(&mut self, loop_detection: LoopDetection) -> &mut Self {
+        self.loop_detection = loop_detection;
+        self
+    }
+
+    pub fn registers(&self) -> &[String] {
+        &self.registers
+    }
+
+    fn operand(&self, kind: OperandKind, text: &str) -> Option<Operand> {
+        let register = self.registers.iter().position(|name| name == text);
+        match (kind, register) {
+            (OperandKind::Register, Some(register)) | (OperandKind::Value, Some(register)) => {
+                Some(Operand::Register(register))
+            }
+            (OperandKind::Value, None) | (OperandKind::Immediate, None) => {
+                text.parse::<i32>().ok().map(Operand::Immediate)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn parse(&self, input: &str) -> Result<IsaProgram, ParseError> {
+        let mut instructions = Vec::<IsaInstruction>::new();
+        for (i, line) in input.lines().enumerate() {
+            let error = |kind, column, text: &str| ParseError {
+                kind,
+                line: i + 1,
+                column,
+                text: String::from(text),
+            };
+            let mut tokens = tokens(line);
+            let (op_column, op_text) = tokens
+                .next()
+                .ok_or_else(|| error(ParseErrorKind::MissingOp, 1, line))?;
+            let operation = self
+                .operations
+                .get(op_text)
+                .ok_or_else(|| error(ParseErrorKind::UnknownOp, op_column, op_text))?;
+
+            let mut operands = Vec::<Operand>::new();
+            for &kind in operation.operands() {
+                let (column, text) = tokens.next().ok_or_else(|| {
+                    let end = line.trim_end().chars().count() + 1;
+                    error(ParseErrorKind::MissingArgument, end, line.trim())
+                })?;
+                operands.push(
+                    self.operand(kind, text)
+                        .ok_or_else(|| error(ParseErrorKind::InvalidArgument, column, text))?,
+                );
+            }
+            if let Some((column, text)) = tokens.next() {
+                return Err(error(ParseErrorKind::TrailingInput, column, text));
+            }
+            instructions.push(IsaInstruction {
+                operation: Arc::clone(operation),
+                operands,
+            });
+        }
+        Ok(IsaProgram {
+            instructions,
+            registers: self.registers.len(),
+            loop_detection: self.loop_detection,
+        })
+    }
+
+    // a console program on this instruction set, fails if it uses ops the set doesn't have
+    pub fn from_program(&self, program: &Program) -> Result<IsaProgram, ParseError> {
+        self.parse(&program.to_string())
+    }
+}
+
+#[derive(Clone)]
+pub struct IsaInstruction {
+    operation: Arc<dyn Operation>,
+    operands: Vec<Operand>,
+}
+
+impl IsaInstruction {
+    pub fn mnemonic(&self) -> &str {
+        self.operation.mnemonic()
+    }
+
+    pub fn operands(&self) -> &[Operand] {
+        &self.operands
+    }
+}
+
+impl fmt::Debug for IsaInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:?}", self.mnemonic(), self.operands)
+    }
+}
+
+// a state that doesn't fit the machine it was given to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    Registers { expected: usize, found: usize },
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::Registers { expected, found } => write!(
+                f,
+                "state has {} registers, the machine has {}",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+#[derive(Debug, Clone)]
+pub struct IsaProgram {
+    instructions: Vec<IsaInstruction>,
+    registers: usize,
+    loop_detection: LoopDetection,
+}
+
+impl IsaProgram {
+    pub fn instructions(&self) -> &[IsaInstruction] {
+        &self.instructions
+    }
+
+    pub fn run(&self, max_steps: Option<usize>) -> Termination<MachineState> {
+        self.run_checked(MachineState::new(self.registers), max_steps)
+    }
+
+    // loop detection only considers states reached during this call. the state has to have as
+    // many registers as the isa the program was built with
+    pub fn run_from(
+        &self,
+        state: MachineState,
+        max_steps: Option<usize>,
+    ) -> Result<Termination<MachineState>, StateError> {
+        if state.registers.len() != self.registers {
+            return Err(StateError::Registers {
+                expected: self.registers,
+                found: state.registers.len(),
+            });
+        }
+        Ok(self.run_checked(state, max_steps))
+    }
+
+    // checks the step limit before anything else, in the same order as Program::run_with, so
+    // both report the same termination for the same budget
+    fn run_checked(
+        &self,
+        mut state: MachineState,
+        max_steps: Option<usize>,
+    ) -> Termination<MachineState> {
+        let len = self.instructions.len();
+        let mut gas = max_steps.unwrap_or(usize::MAX);
+        let mut visited = VisitedSet::with_capacity(len);
+        let mut brent = Brent::new();
+        state.fault = None;
+
+        loop {
+            let pc = state.pc;
+            if gas == 0 && pc != len {
+                return Termination::StepLimitExceeded(state);
+            }
+            if pc == len {
+                return Termination::Halted(state);
+            }
+            let instruction = match self.instructions.get(pc) {
+                Some(instruction) => instruction,
+                None => {
+                    return Termination::OutOfBounds {
+                        pc,
+                        target: pc as i64,
+                        state,
+                    }
+                }
+            };
+            let repeated = match self.loop_detection {
+                LoopDetection::RevisitedInstruction => !visited.insert(pc),
+                LoopDetection::RepeatedState => brent.repeated(&state),
+                LoopDetection::None => false,
+            };
+            if repeated {
+                return Termination::InfiniteLoop {
+                    pc,
+                    revisited_at_step: state.steps,
+                    state,
+                };
+            }
+
+            let checkpoint = Checkpoint::new(&state);
+            let target = match instruction
+                .operation
+                .execute(&instruction.operands, &mut state)
+            {
+                Ok(Flow::Next) => pc as i64 + 1,
+                Ok(Flow::Jump(offset)) => pc as i64 + offset,
+                Ok(Flow::Goto(target)) => target as i64,
+                Ok(Flow::Halt) => {
+                    state.steps += 1;
+                    return Termination::Halted(state);
+                }
+                Err(fault) => {
+                    checkpoint.restore(&mut state);
+                    state.fault = Some(fault);
+                    return Termination::InvalidInstruction { pc, state };
+                }
+            };
+            if target < 0 || target > len as i64 {
+                checkpoint.restore(&mut state);
+                return Termination::OutOfBounds { pc, target, state };
+            }
+            state.pc = target as usize;
+            state.steps += 1;
+            gas -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_support::SAMPLE;
+    use super::*;
+
+    #[test]
+    fn default_profile_matches_console() {
+        let program = Program::parse(SAMPLE).unwrap();
+        let isa_program = Isa::default().from_program(&program).unwrap();
+        match isa_program.run(None) {
+            Termination::InfiniteLoop {
+                pc,
+                revisited_at_step,
+                state,
+            } => assert_eq!((pc, revisited_at_step, state.registers[0]), (1, 7, 5)),
+            _ => panic!("expected a loop"),
+        }
+
+        let mut fixed = program.clone();
+        program.repairs()[0].apply(&mut fixed);
+        let isa_fixed = Isa::default().from_program(&fixed).unwrap();
+        assert_eq!(isa_fixed.run(None).into_state().registers[0], 8);
+
+        // the extended ops aren't part of the default profile
+        assert_eq!(
+            Isa::default().parse("hlt").err().unwrap().kind,
+            ParseErrorKind::UnknownOp
+        );
+    }
+
+    #[test]
+    fn extended() {
+        // acc = 5! using a loop and a call
+        let source = "mov a 5
+                      mov acc 1
+                      call +4
+                      add a -1
+                      jnz a -2
+                      hlt
+                      mul acc a
+                      ret";
+        let program = Isa::extended().parse(source).unwrap();
+        let termination = program.run(Some(1000));
+        assert!(termination.is_halted());
+        assert_eq!(termination.state().registers[0], 120);
+
+        // looping with changing registers isn't a loop, repeating a state is
+        let program = Isa::extended().parse("add a 1\njmp -1").unwrap();
+        assert!(matches!(
+            program.run(Some(100)),
+            Termination::StepLimitExceeded(_)
+        ));
+        let program = Isa::extended().parse("jz b +0").unwrap();
+        assert!(matches!(
+            program.run(None),
+            Termination::InfiniteLoop { pc: 0, .. }
+        ));
+
+        let program = Isa::extended().parse("ret").unwrap();
+        match program.run(None) {
+            Termination::InvalidInstruction { state, .. } => {
+                assert_eq!(state.fault, Some(Fault::StackUnderflow))
+            }
+            _ => panic!("expected a fault"),
+        }
+    }
+
+    #[test]
+    fn deep_recursion() {
+        // every call repeats the pc with a deeper stack, so no state repeats until the stack is full
+        let program = Isa::extended().parse("call +0").unwrap();
+        match program.run(None) {
+            Termination::InvalidInstruction { state, .. } => {
+                assert_eq!(state.fault, Some(Fault::StackOverflow));
+                assert_eq!(state.stack.len(), MAX_CALL_DEPTH);
+                assert_eq!(state.steps, MAX_CALL_DEPTH);
+            }
+            _ => panic!("expected a stack overflow"),
+        }
+
+        // a loop entered at depth still shows up as a repeated state
+        let program = Isa::extended()
+            .parse("call +1\ncall +1\nadd a 1\njz b -1")
+            .unwrap();
+        assert!(matches!(
+            program.run(Some(1000)),
+            Termination::StepLimitExceeded(_)
+        ));
+        let program = Isa::extended().parse("call +1\ncall +1\njz b +0").unwrap();
+        match program.run(None) {
+            Termination::InfiniteLoop { pc, state, .. } => {
+                assert_eq!((pc, state.stack), (2, vec![1, 2]))
+            }
+            _ => panic!("expected a loop"),
+        }
+
+        // a jump out of bounds leaves the stack as it was before the call
+        let program = Isa::extended().parse("call +1\ncall +5").unwrap();
+        match program.run(None) {
+            Termination::OutOfBounds { pc, state, .. } => {
+                assert_eq!((pc, state.stack), (1, vec![1]))
+            }
+            _ => panic!("expected out of bounds"),
+        }
+    }
+
+    #[test]
+    fn run_from() {
+        let program = Isa::extended().parse("add a 1\nhlt").unwrap();
+        let mut state = MachineState::new(5);
+        state.registers[1] = 41;
+        let termination = program.run_from(state, None).unwrap();
+        assert_eq!(termination.state().registers[1], 42);
+
+        assert_eq!(
+            program.run_from(MachineState::new(1), None).err(),
+            Some(StateError::Registers {
+                expected: 5,
+                found: 1
+            })
+        );
+
+        // the step limit is checked ahead of the loop, like the console does
+        let looping = Program::parse("jmp +0").unwrap();
+        let isa_program = Isa::default().from_program(&looping).unwrap();
+        assert!(matches!(
+            looping.run_from(&Default::default(), Some(1)),
+            Termination::StepLimitExceeded(_)
+        ));
+        assert!(matches!(
+            isa_program.run(Some(1)),
+            Termination::StepLimitExceeded(_)
+        ));
+        assert!(matches!(
+            isa_program.run(Some(2)),
+            Termination::InfiniteLoop { .. }
+        ));
+    }
+
+    struct Swap;
+
+    impl Operation for Swap {
+        fn mnemonic(&self) -> &str {
+            "swp"
+        }
+        fn operands(&self) -> &[OperandKind] {
+            &[OperandKind::Register, OperandKind::Register]
+        }
+        fn execute(&self, operands: &[Operand], state: &mut MachineState) -> Result<Flow, Fault> {
+            let a = state.value(operands[0]);
+            let b = state.value(operands[1]);
+            *state.register(operands[0]) = b;
+            *state.register(operands[1]) = a;
+            Ok(Flow::Next)
+        }
+    }
+
+    #[test]
+    fn custom_instruction() {
+        let mut isa = Isa::default();
+        isa.set_registers(&["x"]).register(Mov).register(Swap);
+        let program = isa.parse("mov x 7\nswp acc x").unwrap();
+        assert_eq!(program.run(None).into_state().registers, vec![7, 0]);
+
+        let error = isa.parse("swp acc 3").err().unwrap();
+        assert_eq!(
+            (error.kind, error.column),
+            (ParseErrorKind::InvalidArgument, 9)
+        );
+    }
+}
@@ -0,0 +1,505 @@
+use super::analysis::Verdict;
+use super::asm::{self, SourceMap, Symbol};
+use super::{Opcode, ParseError, Program};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+// a language server for console assembly (see asm), spoken over stdin/stdout by the
+// console_lsp binary. it keeps full copies of open documents and reassembles on every change:
+//
+//   diagnostics  parse errors, plus warnings from the static analysis for loops, jmps out of
+//                bounds and overflows, and hints for unreachable instructions
+//   hover        the pc of the instruction under the cursor and where a jmp (or a nop, if it
+//                were a jmp) goes
+//   definition   from a label or constant to where it's defined, and from any jmp to its target
+//
+// lsp lines and characters are 0-based where the assembler's are 1-based, and columns are
+// counted in chars, which matches utf-16 for the ascii these files are written in
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+const ERROR: u8 = 1;
+const WARNING: u8 = 2;
+const HINT: u8 = 4;
+const UNNECESSARY: u8 = 1;
+
+struct Document {
+    assembled: Result<(Program, SourceMap), ParseError>,
+}
+
+impl Document {
+    fn new(text: &str) -> Self {
+        Document {
+            assembled: asm::assemble_mapped(text),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Server {
+    documents: HashMap<String, Document>,
+}
+
+fn range(line: usize, column: usize, len: usize) -> Value {
+    json!({
+        "start": { "line": line - 1, "character": column - 1 },
+        "end": { "line": line - 1, "character": column - 1 + len },
+    })
+}
+
+// the whole `op arg` text of the instruction at pc
+fn instruction_range(map: &SourceMap, pc: usize) -> Value {
+    let source = &map.instructions[pc];
+    let len = source.argument_column + source.argument.chars().count() - source.column;
+    range(source.line, source.column, len)
+}
+
+fn diagnostic(range: Value, severity: u8, message: String) -> Value {
+    json!({
+        "range": range,
+        "severity": severity,
+        "source": "console",
+        "message": message,
+    })
+}
+
+fn diagnostics(document: &Document) -> Vec<Value> {
+    let (program, map) = match &document.assembled {
+        Ok(assembled) => assembled,
+        Err(e) => {
+            let len = e.text.chars().count().max(1);
+            return vec![diagnostic(
+                range(e.line, e.column, len),
+                ERROR,
+                e.to_string(),
+            )];
+        }
+    };
+
+    let line_of = |pc: usize| map.instructions[pc].line;
+    let mut diagnostics = Vec::<Value>::new();
+    let analysis = program.analyze();
+    match &analysis.verdict {
+        Verdict::Loops {
+            pc,
+            body,
+            accumulator,
+            ..
+        } => {
+            // on the instruction that goes back round
+            let closing = *body.last().unwrap();
+            diagnostics.push(diagnostic(
+                instruction_range(map, closing),
+                WARNING,
+                format!(
+                    "infinite loop: goes back to line {} with acc {}",
+                    line_of(*pc),
+                    accumulator
+                ),
+            ));
+        }
+        Verdict::OutOfBounds { pc, target } => diagnostics.push(diagnostic(
+            instruction_range(map, *pc),
+            WARNING,
+            format!("jumps out of the program, to instruction {}", target),
+        )),
+        Verdict::Overflow { pc, accumulator } => diagnostics.push(diagnostic(
+            instruction_range(map, *pc),
+            WARNING,
+            format!("overflows the accumulator, which is {} here", accumulator),
+        )),
+        Verdict::Halts { .. } | Verdict::AwaitsInput { .. } => {}
+    }
+    for &pc in &analysis.unreachable {
+        let mut hint = diagnostic(
+            instruction_range(map, pc),
+            HINT,
+            String::from("unreachable"),
+        );
+        hint["tags"] = json!([UNNECESSARY]);
+        diagnostics.push(hint);
+    }
+    diagnostics
+}
+
+fn describe_target(program: &Program, map: &SourceMap, target: i64) -> String {
+    let len = program.len() as i64;
+    if target == len {
+        String::from("the end, which halts")
+    } else if target < 0 || target > len {
+        format!("instruction {}, outside the program", target)
+    } else {
+        let target = target as usize;
+        format!(
+            "pc {} on line {}, `{}`",
+            target,
+            map.instructions[target].line,
+            program.instructions()[target]
+        )
+    }
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Server::default()
+    }
+
+    fn publish(&self, uri: &str) -> Value {
+        let diagnostics = match self.documents.get(uri) {
+            Some(document) => diagnostics(document),
+            None => Vec::new(),
+        };
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        })
+    }
+
+    // the document, program and pc at a textDocument/position request's position, with the
+    // 1-based column
+    fn at<'a>(
+        &'a self,
+        params: &Value,
+    ) -> Option<(&'a str, &'a Program, &'a SourceMap, usize, usize)> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        let (key, document) = self.documents.get_key_value(uri)?;
+        let (program, map) = document.assembled.as_ref().ok()?;
+        let line = params["position"]["line"].as_u64()? as usize + 1;
+        let column = params["position"]["character"].as_u64()? as usize + 1;
+        let pc = map.instruction_on_line(line)?;
+        Some((key, program, map, pc, column))
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        let (_, program, map, pc, _) = match self.at(params) {
+            Some(found) => found,
+            None => return Value::Null,
+        };
+        let instruction = program.instructions()[pc];
+        let mut text = format!("pc {}: `{}`", pc, instruction);
+        let target = pc as i64 + instruction.value as i64;
+        match instruction.op {
+            Opcode::Jmp => {
+                text += &format!("\n\njumps to {}", describe_target(program, map, target))
+            }
+            Opcode::Nop => {
+                text += &format!(
+                    "\n\nas a jmp it would go to {}",
+                    describe_target(program, map, target)
+                )
+            }
+            _ => {}
+        }
+        json!({
+            "contents": { "kind": "markdown", "value": text },
+            "range": instruction_range(map, pc),
+        })
+    }
+
+    fn definition(&self, params: &Value) -> Value {
+        let (uri, program, map, pc, column) = match self.at(params) {
+            Some(found) => found,
+            None => return Value::Null,
+        };
+        let source = &map.instructions[pc];
+        let on_argument = column >= source.argument_column
+            && column < source.argument_column + source.argument.chars().count();
+        if on_argument {
+            if let Some(definition) = map.symbol(&source.argument) {
+                return json!({
+                    "uri": uri,
+                    "range": range(definition.line, definition.column, definition.name.chars().count()),
+                });
+            }
+        }
+
+        let instruction = program.instructions()[pc];
+        if instruction.op != Opcode::Jmp {
+            return Value::Null;
+        }
+        let target = pc as i64 + instruction.value as i64;
+        if target >= 0 && (target as usize) < program.len() {
+            return json!({ "uri": uri, "range": instruction_range(map, target as usize) });
+        }
+        // a jmp to the end can still land on a label after the last instruction
+        if target != program.len() as i64 {
+            return Value::Null;
+        }
+        let end = map
+            .symbols
+            .iter()
+            .find(|definition| definition.symbol == Symbol::Label(program.len()));
+        match end {
+            Some(definition) => json!({
+                "uri": uri,
+                "range": range(definition.line, definition.column, definition.name.chars().count()),
+            }),
+            None => Value::Null,
+        }
+    }
+
+    // handles one message and returns the messages to send back, or None once the client says
+    // exit
+    pub fn handle(&mut self, message: &Value) -> Option<Vec<Value>> {
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().map(String::from);
+
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                },
+                "serverInfo": { "name": "console-lsp" },
+            }),
+            "shutdown" => Value::Null,
+            "exit" => return None,
+            "textDocument/hover" => self.hover(params),
+            "textDocument/definition" => self.definition(params),
+            "textDocument/didOpen" | "textDocument/didChange" => {
+                let text = match method {
+                    "textDocument/didOpen" => params["textDocument"]["text"].as_str(),
+                    // full sync, so the last change is the whole document
+                    _ => params["contentChanges"]
+                        .as_array()
+                        .and_then(|changes| changes.last())
+                        .and_then(|change| change["text"].as_str()),
+                };
+                return Some(match (uri, text) {
+                    (Some(uri), Some(text)) => {
+                        self.documents.insert(uri.clone(), Document::new(text));
+                        vec![self.publish(&uri)]
+                    }
+                    _ => Vec::new(),
+                });
+            }
+            "textDocument/didClose" => {
+                return Some(match uri {
+                    Some(uri) => {
+                        self.documents.remove(&uri);
+                        vec![self.publish(&uri)]
+                    }
+                    None => Vec::new(),
+                });
+            }
+            _ => {
+                // notifications we don't handle are dropped, requests get an error
+                return Some(match message.get("id") {
+                    Some(id) => vec![json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": METHOD_NOT_FOUND, "message": format!("unknown method {:?}", method) },
+                    })],
+                    None => Vec::new(),
+                });
+            }
+        };
+
+        Some(match message.get("id") {
+            Some(id) => vec![json!({ "jsonrpc": "2.0", "id": id, "result": result })],
+            None => Vec::new(),
+        })
+    }
+}
+
+// reads one `Content-Length` framed message, None at the end of input. a header block without a
+// length is skipped, and a body that isn't json comes back as Err so the server can answer it
+// and carry on. only the transport failing is an io error
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<serde_json::Result<Value>>> {
+    loop {
+        let mut length = None;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 {
+                return Ok(None);
+            }
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            // anything before it is left over from a skipped message
+            if let Some(start) = header.find("Content-Length:") {
+                length = header[start + "Content-Length:".len()..]
+                    .trim()
+                    .parse::<usize>()
+                    .ok();
+            }
+        }
+        if let Some(length) = length {
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body)?;
+            return Ok(Some(serde_json::from_slice(&body)));
+        }
+    }
+}
+
+fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+// runs the server until the client sends exit or closes the input
+pub fn serve(mut reader: impl BufRead, mut writer: impl Write) -> io::Result<()> {
+    let mut server = Server::new();
+    while let Some(message) = read_message(&mut reader)? {
+        let replies = match message {
+            Ok(message) => match server.handle(&message) {
+                Some(replies) => replies,
+                None => break,
+            },
+            Err(e) => vec![json!({
+                "jsonrpc": "2.0",
+                "id": null,
+                "error": { "code": PARSE_ERROR, "message": e.to_string() },
+            })],
+        };
+        for reply in replies {
+            write_message(&mut writer, &reply)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+start: nop +0
+       acc +1
+       jmp skip
+       acc +3
+back:  jmp -3
+       acc -99
+skip:  acc +1
+       jmp back
+       acc +6
+";
+
+    fn open(server: &mut Server, text: &str) -> Vec<Value> {
+        let replies = server
+            .handle(&json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didOpen",
+                "params": { "textDocument": { "uri": "file:///a.console", "text": text } },
+            }))
+            .unwrap();
+        replies[0]["params"]["diagnostics"]
+            .as_array()
+            .unwrap()
+            .clone()
+    }
+
+    fn request(server: &mut Server, method: &str, line: usize, character: usize) -> Value {
+        let replies = server
+            .handle(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": {
+                    "textDocument": { "uri": "file:///a.console" },
+                    "position": { "line": line, "character": character },
+                },
+            }))
+            .unwrap();
+        replies[0]["result"].clone()
+    }
+
+    #[test]
+    fn diagnostics() {
+        let mut server = Server::new();
+        let diagnostics = open(&mut server, "acc +1\nhop +2\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0]["severity"], 1);
+        assert_eq!(diagnostics[0]["range"], range(2, 1, 3));
+
+        let diagnostics = open(&mut server, SOURCE);
+        let loop_warning = &diagnostics[0];
+        assert_eq!(loop_warning["severity"], 2);
+        assert_eq!(
+            loop_warning["message"],
+            "infinite loop: goes back to line 2 with acc 2"
+        );
+        // `jmp -3` on line 5, after the label
+        assert_eq!(loop_warning["range"], range(5, 8, 6));
+        let unreachable = diagnostics[1..]
+            .iter()
+            .map(|d| d["range"]["start"]["line"].as_u64().unwrap())
+            .collect::<Vec<u64>>();
+        assert_eq!(unreachable, vec![3, 5, 8]);
+    }
+
+    #[test]
+    fn hover_and_definition() {
+        let mut server = Server::new();
+        open(&mut server, SOURCE);
+        let hover = request(&mut server, "textDocument/hover", 4, 9);
+        assert_eq!(
+            hover["contents"]["value"],
+            "pc 4: `jmp -3`\n\njumps to pc 1 on line 2, `acc +1`"
+        );
+
+        // from the label argument to the label, and from a numeric jmp to its target
+        let definition = request(&mut server, "textDocument/definition", 2, 12);
+        assert_eq!(definition["range"], range(7, 1, 4));
+        let definition = request(&mut server, "textDocument/definition", 4, 8);
+        assert_eq!(definition["range"], range(2, 8, 6));
+        assert_eq!(
+            request(&mut server, "textDocument/definition", 1, 8),
+            Value::Null
+        );
+    }
+
+    #[test]
+    fn framing() {
+        let messages = [
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "shutdown" }),
+            json!({ "jsonrpc": "2.0", "method": "exit" }),
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "shutdown" }),
+        ];
+        let mut input = Vec::<u8>::new();
+        for message in &messages {
+            write_message(&mut input, message).unwrap();
+        }
+        let mut output = Vec::<u8>::new();
+        serve(input.as_slice(), &mut output).unwrap();
+
+        let mut reader = output.as_slice();
+        let initialized = read_message(&mut reader).unwrap().unwrap().unwrap();
+        assert_eq!(initialized["result"]["capabilities"]["hoverProvider"], true);
+        let shutdown = read_message(&mut reader).unwrap().unwrap().unwrap();
+        assert_eq!(shutdown["id"], 2);
+        // nothing after exit
+        assert!(read_message(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn malformed() {
+        // a body that isn't json, then headers without a length, then a request that's fine
+        let mut input = Vec::<u8>::new();
+        write!(input, "Content-Length: 8\r\n\r\n{{\"id\": 1").unwrap();
+        write!(input, "Content-Type: text/plain\r\n\r\n").unwrap();
+        write_message(
+            &mut input,
+            &json!({ "jsonrpc": "2.0", "id": 2, "method": "shutdown" }),
+        )
+        .unwrap();
+        let mut output = Vec::<u8>::new();
+        serve(input.as_slice(), &mut output).unwrap();
+
+        let mut reader = output.as_slice();
+        let error = read_message(&mut reader).unwrap().unwrap().unwrap();
+        assert_eq!(error["id"], Value::Null);
+        assert_eq!(error["error"]["code"], PARSE_ERROR);
+        let shutdown = read_message(&mut reader).unwrap().unwrap().unwrap();
+        assert_eq!(shutdown["id"], 2);
+        assert!(read_message(&mut reader).unwrap().is_none());
+    }
+}
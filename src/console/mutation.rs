@@ -0,0 +1,366 @@
+use super::cfg::ControlFlowGraph;
+use super::{Instruction, Opcode, Program, ProgramState, Termination};
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::ops::RangeInclusive;
+
+// ops a ChangeOp can switch to, in and out are never introduced
+const OPCODES: [Opcode; 3] = [Opcode::Nop, Opcode::Acc, Opcode::Jmp];
+
+// a single edit to a program. indices refer to the program the mutation is applied to, so in a
+// sequence of mutations each one sees the shifts made by earlier inserts and deletes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mutation {
+    ChangeOp { pc: usize, op: Opcode },
+    ChangeValue { pc: usize, value: i32 },
+    Delete { pc: usize },
+    Insert { pc: usize, instruction: Instruction },
+}
+
+impl Mutation {
+    // panics if pc is out of bounds, like indexing (insert accepts pc == len)
+    pub fn apply(&self, program: &mut Program) {
+        match *self {
+            Mutation::ChangeOp { pc, op } => program.instructions[pc].op = op,
+            Mutation::ChangeValue { pc, value } => program.instructions[pc].value = value,
+            Mutation::Delete { pc } => {
+                program.instructions.remove(pc);
+            }
+            Mutation::Insert { pc, instruction } => program.instructions.insert(pc, instruction),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MutationKinds {
+    pub change_op: bool,
+    pub change_value: bool,
+    pub delete: bool,
+    pub insert: bool,
+}
+
+impl MutationKinds {
+    pub fn all() -> Self {
+        MutationKinds {
+            change_op: true,
+            change_value: true,
+            delete: true,
+            insert: true,
+        }
+    }
+}
+
+// defaults to op changes only, which covers the nop/jmp twiddle
+impl Default for MutationKinds {
+    fn default() -> Self {
+        MutationKinds {
+            change_op: true,
+            change_value: false,
+            delete: false,
+            insert: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    pub max_edits: usize,
+    pub kinds: MutationKinds,
+    // arguments tried by ChangeValue and Insert
+    pub value_range: RangeInclusive<i32>,
+    // only accept programs that halt with this accumulator
+    pub target_accumulator: Option<i32>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            max_edits: 2,
+            kinds: MutationKinds::default(),
+            value_range: -3..=3,
+            target_accumulator: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Solution {
+    pub mutations: Vec<Mutation>,
+    pub program: Program,
+    pub state: ProgramState,
+}
+
+struct Candidate {
+    mutations: Vec<Mutation>,
+    program: Program,
+    termination: Termination,
+}
+
+impl Candidate {
+    fn new(mutations: Vec<Mutation>, program: Program) -> Self {
+        let termination = program.run();
+        Candidate {
+            mutations,
+            program,
+            termination,
+        }
+    }
+
+    fn solves(&self, options: &SearchOptions) -> bool {
+        match &self.termination {
+            Termination::Halted(state) => options
+                .target_accumulator
+                .is_none_or(|target| state.accumulator == target),
+            _ => false,
+        }
+    }
+}
+
+// single mutations that can change how the program runs. changing an instruction only matters
+// if it's executed. deleting one also matters if it sits between an executed jmp and its target,
+// since the jmp then lands somewhere else, and an insert can shift things anywhere up to the end
+fn mutations(program: &Program, options: &SearchOptions) -> Vec<Mutation> {
+    let len = program.len();
+    let path = ControlFlowGraph::new(program).execution_path();
+    let kinds = &options.kinds;
+
+    let mut mutations = Vec::<Mutation>::new();
+    let mut executed = path.clone();
+    executed.sort_unstable();
+    for &pc in &executed {
+        let instruction = program.instructions[pc];
+        if kinds.change_op {
+            mutations.extend(
+                OPCODES
+                    .iter()
+                    .filter(|&&op| op != instruction.op)
+                    .map(|&op| Mutation::ChangeOp { pc, op }),
+            );
+        }
+        if kinds.change_value {
+            mutations.extend(
+                options
+                    .value_range
+                    .clone()
+                    .filter(|&value| value != instruction.value)
+                    .map(|value| Mutation::ChangeValue { pc, value }),
+            );
+        }
+    }
+    if kinds.delete {
+        let mut deletable = vec![false; len];
+        for &pc in &path {
+            deletable[pc] = true;
+            let instruction = program.instructions[pc];
+            if instruction.op == Opcode::Jmp {
+                let target = pc as i64 + instruction.value as i64;
+                let from = target.min(pc as i64).max(0) as usize;
+                let to = target.max(pc as i64).min(len as i64 - 1) as usize;
+                for crossed in &mut deletable[from..=to] {
+                    *crossed = true;
+                }
+            }
+        }
+        mutations.extend(
+            (0..len)
+                .filter(|&pc| deletable[pc])
+                .map(|pc| Mutation::Delete { pc }),
+        );
+    }
+    if kinds.insert {
+        for pc in 0..=len {
+            for &op in OPCODES.iter() {
+                mutations.extend(options.value_range.clone().map(|value| Mutation::Insert {
+                    pc,
+                    instruction: Instruction { op, value },
+                }));
+            }
+        }
+    }
+    mutations
+}
+
+impl Program {
+    // breadth first over edit count, so the first solution found uses the fewest mutations.
+    // each level is expanded and run in parallel, and programs already seen are skipped.
+    // returns None if nothing within options.max_edits works
+    pub fn search_mutations(&self, options: &SearchOptions) -> Option<Solution> {
+        let root = Candidate::new(Vec::new(), self.clone());
+        let mut seen = HashSet::<Vec<Instruction>>::new();
+        seen.insert(self.instructions.clone());
+        let mut frontier = vec![root];
+
+        for edits in 0..=options.max_edits {
+            if let Some(found) = frontier.par_iter().find_first(|c| c.solves(options)) {
+                return Some(Solution {
+                    mutations: found.mutations.clone(),
+                    program: found.program.clone(),
+                    state: found.termination.state().clone(),
+                });
+            }
+            if edits == options.max_edits {
+                break;
+            }
+
+            let children = frontier
+                .par_iter()
+                .flat_map_iter(|parent| {
+                    mutations(&parent.program, options)
+                        .into_iter()
+                        .map(move |mutation| {
+                            let mut program = parent.program.clone();
+                            mutation.apply(&mut program);
+                            (parent, mutation, program)
+                        })
+                })
+                .collect::<Vec<(&Candidate, Mutation, Program)>>();
+
+            let unseen = children
+                .into_iter()
+                .filter(|(_, _, program)| seen.insert(program.instructions.clone()))
+                .collect::<Vec<(&Candidate, Mutation, Program)>>();
+
+            frontier = unseen
+                .into_par_iter()
+                .map(|(parent, mutation, program)| {
+                    let mut mutations = parent.mutations.clone();
+                    mutations.push(mutation);
+                    Candidate::new(mutations, program)
+                })
+                .collect();
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_support::{instruction_with, SAMPLE};
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn single_swap() {
+        let program = Program::parse(SAMPLE).unwrap();
+        let solution = program.search_mutations(&SearchOptions::default()).unwrap();
+        assert_eq!(
+            solution.mutations,
+            vec![Mutation::ChangeOp {
+                pc: 7,
+                op: Opcode::Nop
+            }]
+        );
+        assert_eq!(solution.state.accumulator, 8);
+    }
+
+    #[test]
+    fn target_accumulator() {
+        let program = Program::parse(SAMPLE).unwrap();
+        let options = SearchOptions {
+            kinds: MutationKinds::all(),
+            value_range: 0..=6,
+            target_accumulator: Some(12),
+            ..SearchOptions::default()
+        };
+        let solution = program.search_mutations(&options).unwrap();
+        assert_eq!(solution.mutations.len(), 2);
+        assert_eq!(solution.state.accumulator, 12);
+        assert!(solution.program.run().is_halted());
+    }
+
+    #[test]
+    fn edit_budget() {
+        // both jmps have to go before this halts
+        let program = Program::parse("jmp +0\njmp +0").unwrap();
+        let mut options = SearchOptions {
+            max_edits: 1,
+            ..SearchOptions::default()
+        };
+        assert!(program.search_mutations(&options).is_none());
+        options.max_edits = 2;
+        assert_eq!(
+            program.search_mutations(&options).unwrap().mutations.len(),
+            2
+        );
+    }
+
+    #[test]
+    fn apply_mutations() {
+        let mut program = Program::parse("nop +0\nacc +1").unwrap();
+        Mutation::Insert {
+            pc: 1,
+            instruction: Instruction {
+                op: Opcode::Acc,
+                value: 5,
+            },
+        }
+        .apply(&mut program);
+        Mutation::Delete { pc: 0 }.apply(&mut program);
+        Mutation::ChangeValue { pc: 1, value: 2 }.apply(&mut program);
+        assert_eq!(program.run().into_state().accumulator, 7);
+    }
+
+    #[test]
+    fn insert_past_the_path() {
+        // the jmp skips over the end, an instruction inserted after it brings the end into reach
+        let program = Program::parse("jmp +3\nacc +1").unwrap();
+        let options = SearchOptions {
+            max_edits: 1,
+            kinds: MutationKinds {
+                change_op: false,
+                insert: true,
+                ..MutationKinds::default()
+            },
+            value_range: 0..=0,
+            ..SearchOptions::default()
+        };
+        let solution = program.search_mutations(&options).unwrap();
+        assert_eq!(solution.mutations.len(), 1);
+        assert!(solution.program.run().is_halted());
+    }
+
+    // every single edit the search could make, wherever it is
+    fn all_edits(program: &Program, options: &SearchOptions) -> Vec<Mutation> {
+        let len = program.len();
+        let mut edits = Vec::<Mutation>::new();
+        for pc in 0..len {
+            edits.extend(OPCODES.iter().map(|&op| Mutation::ChangeOp { pc, op }));
+            edits.extend(
+                options
+                    .value_range
+                    .clone()
+                    .map(|value| Mutation::ChangeValue { pc, value }),
+            );
+            edits.push(Mutation::Delete { pc });
+        }
+        for pc in 0..=len {
+            for &op in OPCODES.iter() {
+                edits.extend(options.value_range.clone().map(|value| Mutation::Insert {
+                    pc,
+                    instruction: Instruction { op, value },
+                }));
+            }
+        }
+        edits
+    }
+
+    proptest! {
+        #[test]
+        fn single_edits(instructions in prop::collection::vec(instruction_with(-3i32..4), 0..8)) {
+            let program = Program::new(instructions);
+            let options = SearchOptions {
+                max_edits: 1,
+                kinds: MutationKinds::all(),
+                value_range: -2..=2,
+                target_accumulator: None,
+            };
+            let fixable = program.run().is_halted()
+                || all_edits(&program, &options).iter().any(|mutation| {
+                    let mut edited = program.clone();
+                    mutation.apply(&mut edited);
+                    edited.run().is_halted()
+                });
+            prop_assert_eq!(program.search_mutations(&options).is_some(), fixable);
+        }
+    }
+}
@@ -0,0 +1,334 @@
+use super::io::ConsoleIo;
+use super::{Program, ProgramState, Termination};
+use rayon::prelude::*;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
+// several console programs talking through addressed queues. `out +N` sends the accumulator to
+// node N's inbox (or, if there's no node N, to the network's external output), and `in` reads
+// the node's own inbox, blocking while it's empty
+
+pub const DEFAULT_QUANTUM: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Message {
+    pub from: usize,
+    pub to: i32,
+    pub value: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    // one node at a time, messages are delivered as soon as a node's slice ends
+    RoundRobin,
+    // every runnable node's slice runs at once on rayon threads, messages are delivered after
+    // the round in node order
+    Parallel,
+}
+
+#[derive(Debug, Clone)]
+pub enum NodeStatus {
+    Runnable,
+    AwaitingInput,
+    Stopped(Termination),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkOutcome {
+    // every node halted
+    Finished,
+    // these nodes crashed: jumped out of bounds or hit an invalid instruction. reported ahead of
+    // a deadlock, since whatever is left waiting is likely waiting on them
+    Failed {
+        nodes: Vec<usize>,
+    },
+    // nothing can make progress: each node still running is blocked on an empty inbox or looped
+    Deadlock {
+        blocked: Vec<usize>,
+        looping: Vec<usize>,
+    },
+    RoundLimitExceeded,
+}
+
+struct Node {
+    program: Program,
+    state: ProgramState,
+    inbox: VecDeque<i32>,
+    status: NodeStatus,
+}
+
+struct NodeIo<'a> {
+    from: usize,
+    inbox: &'a mut VecDeque<i32>,
+    outbox: &'a mut Vec<Message>,
+}
+
+impl ConsoleIo for NodeIo<'_> {
+    fn input(&mut self, _: i32) -> Option<i32> {
+        self.inbox.pop_front()
+    }
+
+    fn output(&mut self, port: i32, value: i32) {
+        self.outbox.push(Message {
+            from: self.from,
+            to: port,
+            value,
+        });
+    }
+}
+
+impl Node {
+    // runs one slice, returning what the node sent
+    fn run_slice(&mut self, address: usize, quantum: usize) -> Vec<Message> {
+        let mut outbox = Vec::<Message>::new();
+        if let NodeStatus::Runnable = self.status {
+            let mut io = NodeIo {
+                from: address,
+                inbox: &mut self.inbox,
+                outbox: &mut outbox,
+            };
+            self.status = match self.program.run_io(&self.state, Some(quantum), &mut io) {
+                Termination::StepLimitExceeded(state) => {
+                    self.state = state;
+                    NodeStatus::Runnable
+                }
+                Termination::AwaitingInput { state, .. } => {
+                    self.state = state;
+                    NodeStatus::AwaitingInput
+                }
+                termination => {
+                    self.state = termination.state().clone();
+                    NodeStatus::Stopped(termination)
+                }
+            };
+        }
+        outbox
+    }
+}
+
+pub struct Network {
+    nodes: Vec<Node>,
+    quantum: usize,
+    external: Vec<Message>,
+}
+
+impl Network {
+    pub fn new(programs: Vec<Program>) -> Self {
+        Network {
+            nodes: programs
+                .into_iter()
+                .map(|program| Node {
+                    program,
+                    state: ProgramState::new(),
+                    inbox: VecDeque::new(),
+                    status: NodeStatus::Runnable,
+                })
+                .collect(),
+            quantum: DEFAULT_QUANTUM,
+            external: Vec::new(),
+        }
+    }
+
+    // steps each node runs before the scheduler moves on
+    pub fn set_quantum(&mut self, quantum: usize) {
+        self.quantum = quantum.max(1);
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // queues a value for a node from outside the network, false if there's no such node
+    pub fn send(&mut self, to: usize, value: i32) -> bool {
+        let node = match self.nodes.get_mut(to) {
+            Some(node) => node,
+            None => return false,
+        };
+        node.inbox.push_back(value);
+        if let NodeStatus::AwaitingInput = node.status {
+            node.status = NodeStatus::Runnable;
+        }
+        true
+    }
+
+    pub fn state(&self, node: usize) -> Option<&ProgramState> {
+        self.nodes.get(node).map(|node| &node.state)
+    }
+
+    pub fn status(&self, node: usize) -> Option<&NodeStatus> {
+        self.nodes.get(node).map(|node| &node.status)
+    }
+
+    // messages sent to addresses that aren't nodes
+    pub fn external_output(&self) -> &[Message] {
+        &self.external
+    }
+
+    fn deliver(&mut self, message: Message) {
+        let delivered = usize::try_from(message.to).is_ok_and(|to| self.send(to, message.value));
+        if !delivered {
+            self.external.push(message);
+        }
+    }
+
+    // runs rounds until every node has stopped or is stuck, or max_rounds is used up
+    pub fn run(&mut self, schedule: Schedule, max_rounds: Option<usize>) -> NetworkOutcome {
+        let mut rounds = 0;
+        loop {
+            let runnable = self
+                .nodes
+                .iter()
+                .any(|node| matches!(node.status, NodeStatus::Runnable));
+            if !runnable {
+                return self.outcome();
+            }
+            if max_rounds.is_some_and(|max| rounds == max) {
+                return NetworkOutcome::RoundLimitExceeded;
+            }
+
+            let quantum = self.quantum;
+            match schedule {
+                Schedule::RoundRobin => {
+                    for address in 0..self.nodes.len() {
+                        for message in self.nodes[address].run_slice(address, quantum) {
+                            self.deliver(message);
+                        }
+                    }
+                }
+                Schedule::Parallel => {
+                    let sent = self
+                        .nodes
+                        .par_iter_mut()
+                        .enumerate()
+                        .map(|(address, node)| node.run_slice(address, quantum))
+                        .collect::<Vec<Vec<Message>>>();
+                    for message in sent.into_iter().flatten() {
+                        self.deliver(message);
+                    }
+                }
+            }
+            rounds += 1;
+        }
+    }
+
+    fn outcome(&self) -> NetworkOutcome {
+        let mut blocked = Vec::<usize>::new();
+        let mut looping = Vec::<usize>::new();
+        let mut failed = Vec::<usize>::new();
+        for (address, node) in self.nodes.iter().enumerate() {
+            match node.status {
+                NodeStatus::AwaitingInput => blocked.push(address),
+                NodeStatus::Stopped(Termination::InfiniteLoop { .. }) => looping.push(address),
+                NodeStatus::Stopped(Termination::Halted(_)) | NodeStatus::Runnable => {}
+                NodeStatus::Stopped(_) => failed.push(address),
+            }
+        }
+        if !failed.is_empty() {
+            NetworkOutcome::Failed { nodes: failed }
+        } else if blocked.is_empty() && looping.is_empty() {
+            NetworkOutcome::Finished
+        } else {
+            NetworkOutcome::Deadlock { blocked, looping }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connect(sources: &[&str]) -> Network {
+        Network::new(
+            sources
+                .iter()
+                .map(|source| Program::parse(source).unwrap())
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn pipeline() {
+        for &schedule in &[Schedule::RoundRobin, Schedule::Parallel] {
+            let mut network = connect(&["in +0\nacc +1\nout +1", "in +0\nacc +10\nout -1"]);
+            assert!(network.send(0, 5));
+            assert_eq!(network.run(schedule, None), NetworkOutcome::Finished);
+            assert_eq!(
+                network.external_output(),
+                &[Message {
+                    from: 1,
+                    to: -1,
+                    value: 16
+                }]
+            );
+        }
+    }
+
+    #[test]
+    fn deadlocks() {
+        // each waits for the other to speak first
+        let mut network = connect(&["in +0\nout +1", "in +0\nout +0"]);
+        assert_eq!(
+            network.run(Schedule::Parallel, None),
+            NetworkOutcome::Deadlock {
+                blocked: vec![0, 1],
+                looping: vec![]
+            }
+        );
+
+        let mut network = connect(&["nop +0\njmp -1", "in +0"]);
+        network.set_quantum(1);
+        assert_eq!(
+            network.run(Schedule::RoundRobin, None),
+            NetworkOutcome::Deadlock {
+                blocked: vec![1],
+                looping: vec![0]
+            }
+        );
+    }
+
+    #[test]
+    fn failures() {
+        let mut network = connect(&["jmp -5"]);
+        assert_eq!(
+            network.run(Schedule::RoundRobin, None),
+            NetworkOutcome::Failed { nodes: vec![0] }
+        );
+
+        // node 1 overflows, which leaves node 2 waiting on it forever
+        let mut network = connect(&["acc +1", "acc +2147483647\nacc +1\nout +2", "in +0"]);
+        assert_eq!(
+            network.run(Schedule::Parallel, None),
+            NetworkOutcome::Failed { nodes: vec![1] }
+        );
+        assert!(matches!(
+            network.status(1),
+            Some(NodeStatus::Stopped(Termination::InvalidInstruction { .. }))
+        ));
+    }
+
+    #[test]
+    fn ring() {
+        // a counter passed around three nodes forever, each adding its own increment
+        let mut network = connect(&[
+            "in +0\nacc +1\nout +1\njmp -3",
+            "in +0\nacc +10\nout +2\njmp -3",
+            "in +0\nacc +100\nout +0\njmp -3",
+        ]);
+        assert!(network.send(0, 0));
+        assert_eq!(
+            network.run(Schedule::RoundRobin, Some(4)),
+            NetworkOutcome::RoundLimitExceeded
+        );
+        // one lap per round
+        assert_eq!(network.state(2).unwrap().accumulator, 444);
+        assert!(matches!(network.status(1), Some(NodeStatus::AwaitingInput)));
+
+        // addresses past the last node
+        assert!(!network.send(3, 0));
+        assert!(network.state(3).is_none() && network.status(3).is_none());
+    }
+}
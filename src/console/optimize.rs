@@ -0,0 +1,272 @@
+use super::cfg::ControlFlowGraph;
+use super::io::ConsoleIo;
+use super::{Instruction, Opcode, Program, Termination};
+use std::convert::TryFrom;
+use std::mem::discriminant;
+
+// peephole pass for running programs faster. drops instructions the run never reaches and ones
+// that do nothing (nop, acc +0, jmp +1), folds runs of acc into one, and remaps every jmp to the
+// new layout. jmps out of bounds stay out of bounds on the same side.
+//
+// loop detection still fires at the same accumulator: a dropped instruction never changes the
+// accumulator, and a folded run is only entered at its first instruction. the run from pc 0 is
+// deterministic up to the first `in`, so the accumulator before each acc is known and an acc is
+// only folded if it doesn't overflow there. one that does stays on its own and traps with the
+// same accumulator. after an `in` nothing is folded, and neither is anything on a loop through an
+// `in`, since reading clears the visited flags and the loop runs again with what was read.
+// passes repeat until nothing shrinks, since remapping can leave a new jmp +1 behind
+pub fn optimize(program: &Program) -> Program {
+    let mut current = pass(program);
+    loop {
+        let next = pass(&current);
+        if next.len() == current.len() {
+            return next;
+        }
+        current = next;
+    }
+}
+
+fn pass(program: &Program) -> Program {
+    let instructions = &program.instructions;
+    let len = instructions.len();
+    let cfg = ControlFlowGraph::new(program);
+
+    // the accumulator before each instruction the run reaches, None once an `in` has replaced it
+    // or the run has trapped
+    let mut reachable = vec![false; len];
+    let mut accumulator_at = vec![None; len];
+    let mut accumulator = Some(0i64);
+    let path = cfg.execution_path();
+    for &pc in &path {
+        reachable[pc] = true;
+        accumulator_at[pc] = accumulator;
+        let instruction = instructions[pc];
+        accumulator = match instruction.op {
+            Opcode::Acc => accumulator
+                .map(|a| a + instruction.value as i64)
+                .filter(|&a| i32::try_from(a).is_ok()),
+            Opcode::In => None,
+            _ => accumulator,
+        };
+    }
+    // a path that runs back into itself ends in a loop, which runs again after every `in` on it
+    let looped = path.last().and_then(|&pc| cfg.successor(pc));
+    if let Some(start) = looped.and_then(|next| path.iter().position(|&pc| pc == next)) {
+        let cycle = &path[start..];
+        if cycle.iter().any(|&pc| instructions[pc].op == Opcode::In) {
+            cycle.iter().for_each(|&pc| accumulator_at[pc] = None);
+        }
+    }
+    let no_op = |instruction: &Instruction| match instruction.op {
+        Opcode::Nop => true,
+        Opcode::Acc => instruction.value == 0,
+        Opcode::Jmp => instruction.value == 1,
+        Opcode::In | Opcode::Out => false,
+    };
+
+    // a jmp +1 only lands where falling through would
+    let mut targeted = vec![false; len + 1];
+    for pc in (0..len).filter(|&pc| reachable[pc]) {
+        if instructions[pc].op == Opcode::Jmp && !no_op(&instructions[pc]) {
+            if let Some(target) = cfg.successor(pc) {
+                targeted[target] = true;
+            }
+        }
+    }
+
+    // kept instructions with their original index, and where each original index ends up
+    let mut kept = Vec::<(usize, Instruction)>::new();
+    let mut new_index = vec![0usize; len + 1];
+    // set while everything since the last kept acc was dropped and nothing jumped in between
+    let mut foldable = false;
+    for pc in 0..len {
+        new_index[pc] = kept.len();
+        let instruction = instructions[pc];
+        if targeted[pc] {
+            foldable = false;
+        }
+        if !reachable[pc] || no_op(&instruction) {
+            continue;
+        }
+        let fits =
+            accumulator_at[pc].is_some_and(|a| i32::try_from(a + instruction.value as i64).is_ok());
+        if instruction.op == Opcode::Acc && foldable && fits {
+            let last = &mut kept.last_mut().unwrap().1;
+            if let Some(value) = last.value.checked_add(instruction.value) {
+                last.value = value;
+                continue;
+            }
+        }
+        foldable = instruction.op == Opcode::Acc;
+        kept.push((pc, instruction));
+    }
+    new_index[len] = kept.len();
+
+    let new_len = kept.len() as i64;
+    let optimized = kept
+        .iter()
+        .enumerate()
+        .map(|(new_pc, &(pc, instruction))| {
+            if instruction.op != Opcode::Jmp {
+                return instruction;
+            }
+            let new_pc = new_pc as i64;
+            let new_target = match cfg.successor(pc) {
+                Some(target) => new_index[target] as i64,
+                None if instruction.value < 0 => -1,
+                None => new_len + 1,
+            };
+            Instruction {
+                op: Opcode::Jmp,
+                value: (new_target - new_pc) as i32,
+            }
+        })
+        .collect::<Vec<Instruction>>();
+
+    Program::new(optimized)
+}
+
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    pub original: Termination,
+    pub optimized: Termination,
+}
+
+// runs both programs, each with its own copy of io, and checks they end the same way with the
+// same accumulator and leave their io the same, i.e. read as much and wrote the same things
+pub fn check_equivalent<I: ConsoleIo + Clone + PartialEq>(
+    original: &Program,
+    optimized: &Program,
+    max_steps: Option<usize>,
+    io: &I,
+) -> Result<(), Box<Mismatch>> {
+    let start = Default::default();
+    let (mut io_a, mut io_b) = (io.clone(), io.clone());
+    let a = original.run_io(&start, max_steps, &mut io_a);
+    let b = optimized.run_io(&start, max_steps, &mut io_b);
+    if discriminant(&a) == discriminant(&b)
+        && a.state().accumulator == b.state().accumulator
+        && io_a == io_b
+    {
+        Ok(())
+    } else {
+        Err(Box::new(Mismatch {
+            original: a,
+            optimized: b,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::io::QueueIo;
+    use super::super::test_support::{instruction_with, io_instruction, values, SAMPLE};
+    use super::*;
+    use proptest::prelude::*;
+
+    fn optimized(source: &str) -> String {
+        let program = Program::parse(source).unwrap();
+        let optimized = optimize(&program);
+        check_equivalent(&program, &optimized, None, &QueueIo::new()).unwrap();
+        optimized.to_string()
+    }
+
+    #[test]
+    fn sample() {
+        // acc -99 and acc +6 never run
+        assert_eq!(
+            optimized(SAMPLE),
+            "acc +1\njmp +3\nacc +3\njmp -3\nacc +1\njmp -3\n"
+        );
+    }
+
+    #[test]
+    fn folding() {
+        // the whole run adds up to acc +0, which the next pass drops
+        assert_eq!(
+            optimized("acc +1\nnop +5\nacc +2\njmp +1\nacc -1\nacc -2\nacc +0"),
+            ""
+        );
+        // a jump into the middle of a run keeps it apart
+        assert_eq!(
+            optimized("acc +1\nacc +1\njmp -1"),
+            "acc +1\nacc +1\njmp -1\n"
+        );
+        // skipping the dead acc leaves a jmp +1, which the next pass drops
+        assert_eq!(
+            optimized("acc +1\njmp +2\nacc +9\nacc +1\nout +0"),
+            "acc +2\nout +0\n"
+        );
+        assert_eq!(
+            optimized("acc +2147483647\nacc +1"),
+            "acc +2147483647\nacc +1\n"
+        );
+        // the last acc traps at i32::MAX, folding it in would trap one short of that
+        assert_eq!(
+            optimized("acc +2147483646\nacc +1\nacc +1"),
+            "acc +2147483647\nacc +1\n"
+        );
+        // mixed signs fold as long as nothing overflows on the way
+        assert_eq!(optimized("acc +5\nacc -7\nacc +1"), "acc -1\n");
+        // an `in` leaves the accumulator unknown
+        assert_eq!(
+            optimized("in +0\nacc +1\nacc +1"),
+            "in +0\nacc +1\nacc +1\n"
+        );
+    }
+
+    #[test]
+    fn jumps_out_of_bounds() {
+        assert_eq!(optimized("nop +0\nnop +0\njmp -7"), "jmp -1\n");
+        assert_eq!(optimized("nop +0\njmp +9\nacc +1"), "jmp +2\n");
+        assert_eq!(optimized("jmp +3\nacc +1\nacc +1"), "");
+    }
+
+    #[test]
+    fn input_in_a_loop() {
+        // the second time round the accs run with what was read, 1 - 5 doesn't trap at pc 1
+        // but 1 + i32::MAX does at pc 0
+        let program = Program::parse("acc +2147483647\nacc -5\nin +0\njmp -3").unwrap();
+        let optimized = optimize(&program);
+        assert_eq!(optimized, program);
+        let io = QueueIo::with_input(vec![1]);
+        check_equivalent(&program, &optimized, None, &io).unwrap();
+        assert!(matches!(
+            optimized.run_io(&Default::default(), None, &mut io.clone()),
+            Termination::InvalidInstruction { pc: 0, .. }
+        ));
+
+        // and with outputs in between
+        let program = Program::parse("acc +3\nacc +4\nout +0\nin +0\njmp -4").unwrap();
+        let optimized = optimize(&program);
+        check_equivalent(
+            &program,
+            &optimized,
+            None,
+            &QueueIo::with_input(vec![5, -2]),
+        )
+        .unwrap();
+    }
+
+    proptest! {
+        #[test]
+        fn equivalent(instructions in prop::collection::vec(instruction_with(-4i32..5), 0..40)) {
+            let program = Program::new(instructions);
+            let optimized = optimize(&program);
+            prop_assert!(optimized.len() <= program.len());
+            prop_assert!(check_equivalent(&program, &optimized, None, &QueueIo::new()).is_ok());
+        }
+
+        // accs at the extremes, so runs overflow part way through, and ins
+        #[test]
+        fn equivalent_traps(
+            instructions in prop::collection::vec(io_instruction(), 0..40),
+            input in prop::collection::vec(values(), 0..4),
+        ) {
+            let program = Program::new(instructions);
+            let optimized = optimize(&program);
+            let io = QueueIo::with_input(input);
+            prop_assert!(check_equivalent(&program, &optimized, None, &io).is_ok());
+        }
+    }
+}
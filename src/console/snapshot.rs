@@ -0,0 +1,244 @@
+use super::{Instruction, Opcode, Program, ProgramState, Termination};
+use serde::de::{self, Deserializer};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+// a paused run: the program plus the whole state, so it can be written out, read back in
+// another process and resumed with exactly the result the uninterrupted run would give.
+//
+// in json instructions are written the way they're parsed ("acc +3") and the visited set as its
+// bitset words. the binary format is cbor with each instruction as an (opcode, value) pair
+
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+// opcode numbering in the binary format, don't reorder
+const OPCODES: [Opcode; 5] = [
+    Opcode::Nop,
+    Opcode::Acc,
+    Opcode::Jmp,
+    Opcode::In,
+    Opcode::Out,
+];
+
+impl Serialize for Instruction {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            let code = OPCODES.iter().position(|&op| op == self.op).unwrap() as u8;
+            (code, self.value).serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Instruction {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let text = String::deserialize(deserializer)?;
+            Instruction::parse(&text, 1).map_err(de::Error::custom)
+        } else {
+            let (code, value) = <(u8, i32)>::deserialize(deserializer)?;
+            match OPCODES.get(code as usize) {
+                Some(&op) => Ok(Instruction { op, value }),
+                None => Err(de::Error::custom(format!("unknown opcode {}", code))),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    Json,
+    Binary,
+}
+
+impl SnapshotFormat {
+    // json for a .json file, binary for anything else
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        match path.as_ref().extension() {
+            Some(extension) if extension == "json" => SnapshotFormat::Json,
+            _ => SnapshotFormat::Binary,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    // the contents didn't decode, or didn't describe a snapshot
+    Format(String),
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "{}", e),
+            SnapshotError::Format(e) => write!(f, "invalid snapshot: {}", e),
+            SnapshotError::UnsupportedVersion(version) => write!(
+                f,
+                "snapshot version {} isn't supported, expected {}",
+                version, SNAPSHOT_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    version: u32,
+    pub program: Program,
+    pub state: ProgramState,
+}
+
+impl Snapshot {
+    pub fn new(program: &Program, state: &ProgramState) -> Self {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            program: program.clone(),
+            state: state.clone(),
+        }
+    }
+
+    pub fn write(
+        &self,
+        mut writer: impl Write,
+        format: SnapshotFormat,
+    ) -> Result<(), SnapshotError> {
+        match format {
+            SnapshotFormat::Json => {
+                serde_json::to_writer(&mut writer, self)
+                    .map_err(|e| SnapshotError::Format(e.to_string()))?;
+                writeln!(writer)?;
+            }
+            SnapshotFormat::Binary => {
+                ciborium::into_writer(self, &mut writer).map_err(|e| match e {
+                    ciborium::ser::Error::Io(e) => SnapshotError::Io(e),
+                    e => SnapshotError::Format(e.to_string()),
+                })?
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn read(reader: impl Read, format: SnapshotFormat) -> Result<Self, SnapshotError> {
+        let snapshot: Snapshot = match format {
+            SnapshotFormat::Json => {
+                serde_json::from_reader(reader).map_err(|e| SnapshotError::Format(e.to_string()))?
+            }
+            SnapshotFormat::Binary => ciborium::from_reader(reader).map_err(|e| match e {
+                ciborium::de::Error::Io(e) => SnapshotError::Io(e),
+                e => SnapshotError::Format(e.to_string()),
+            })?,
+        };
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(snapshot.version));
+        }
+        if snapshot.state.current_instruction > snapshot.program.len() {
+            return Err(SnapshotError::Format(format!(
+                "pc {} is past the end of a {} instruction program",
+                snapshot.state.current_instruction,
+                snapshot.program.len()
+            )));
+        }
+        Ok(snapshot)
+    }
+
+    // the format is picked from the extension, see SnapshotFormat::from_path
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        let format = SnapshotFormat::from_path(&path);
+        self.write(BufWriter::new(File::create(path)?), format)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        let format = SnapshotFormat::from_path(&path);
+        Snapshot::read(BufReader::new(File::open(path)?), format)
+    }
+
+    pub fn resume(&self, max_steps: Option<usize>) -> Termination {
+        self.program.run_from(&self.state, max_steps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_support::SAMPLE;
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn paused(source: &str, steps: usize) -> Snapshot {
+        let program = Program::parse(source).unwrap();
+        let state = program
+            .run_from(&ProgramState::new(), Some(steps))
+            .into_state();
+        Snapshot::new(&program, &state)
+    }
+
+    #[test]
+    fn json() {
+        let snapshot = paused(SAMPLE, 3);
+        let mut json = Vec::<u8>::new();
+        snapshot.write(&mut json, SnapshotFormat::Json).unwrap();
+        assert_eq!(
+            String::from_utf8(json.clone()).unwrap(),
+            "{\"version\":1,\"program\":[\"nop +0\",\"acc +1\",\"jmp +4\",\"acc +3\",\"jmp -3\",\"acc -99\",\"acc +1\",\"jmp -4\",\"acc +6\"],\"state\":{\"accumulator\":1,\"current_instruction\":6,\"visited_instructions\":[7],\"steps\":3}}\n"
+        );
+        let restored = Snapshot::read(json.as_slice(), SnapshotFormat::Json).unwrap();
+        assert_eq!(restored.program, snapshot.program);
+        assert_eq!(
+            restored.state.visited_instructions,
+            snapshot.state.visited_instructions
+        );
+
+        let bad = "{\"version\":1,\"program\":[\"hop +0\"],\"state\":{\"accumulator\":0,\"current_instruction\":0,\"visited_instructions\":[],\"steps\":0}}";
+        assert!(matches!(
+            Snapshot::read(bad.as_bytes(), SnapshotFormat::Json),
+            Err(SnapshotError::Format(_))
+        ));
+        let future = bad
+            .replace("\"version\":1", "\"version\":2")
+            .replace("hop", "nop");
+        assert!(matches!(
+            Snapshot::read(future.as_bytes(), SnapshotFormat::Json),
+            Err(SnapshotError::UnsupportedVersion(2))
+        ));
+    }
+
+    #[test]
+    fn pause_and_resume() {
+        // long enough that the visited set spans several words
+        let source = (0..300)
+            .map(|i| format!("acc {:+}", i % 5 - 2))
+            .chain(std::iter::once(String::from("jmp -250")))
+            .collect::<Vec<String>>()
+            .join("\n");
+        let uninterrupted = format!("{:?}", Program::parse(&source).unwrap().run());
+
+        let dir = env::temp_dir().join(format!("console-snapshot-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in &["paused.json", "paused.bin"] {
+            let path = dir.join(name);
+            paused(&source, 200).save(&path).unwrap();
+            let resumed = Snapshot::load(&path).unwrap().resume(None);
+            assert_eq!(format!("{:?}", resumed), uninterrupted);
+        }
+        let binary = fs::metadata(dir.join("paused.bin")).unwrap().len();
+        let json = fs::metadata(dir.join("paused.json")).unwrap().len();
+        assert!(binary < json);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
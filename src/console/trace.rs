@@ -0,0 +1,225 @@
+use super::Opcode;
+use std::io::{self, Write};
+
+// one executed instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceStep {
+    pub step: usize,
+    pub pc: usize,
+    pub op: Opcode,
+    pub value: i32,
+    pub accumulator_before: i32,
+    pub accumulator_after: i32,
+}
+
+pub trait Tracer {
+    fn record(&mut self, step: &TraceStep);
+}
+
+// what run_from uses, compiles away to nothing
+pub struct NoTrace;
+
+impl Tracer for NoTrace {
+    fn record(&mut self, _: &TraceStep) {}
+}
+
+// keeps every step in memory
+impl Tracer for Vec<TraceStep> {
+    fn record(&mut self, step: &TraceStep) {
+        self.push(*step);
+    }
+}
+
+// feeds both, e.g. a TraceWriter and a Profile in the same run
+impl<A: Tracer, B: Tracer> Tracer for (A, B) {
+    fn record(&mut self, step: &TraceStep) {
+        self.0.record(step);
+        self.1.record(step);
+    }
+}
+
+impl<T: Tracer + ?Sized> Tracer for &mut T {
+    fn record(&mut self, step: &TraceStep) {
+        (**self).record(step);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    JsonLines,
+    Csv,
+}
+
+// streams steps to a writer as they happen. the first write error stops the output and is
+// returned from finish, since the vm itself can't fail on io
+pub struct TraceWriter<W: Write> {
+    writer: W,
+    format: TraceFormat,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(mut writer: W, format: TraceFormat) -> Self {
+        let error = match format {
+            TraceFormat::Csv => writeln!(writer, "step,pc,op,arg,acc_before,acc_after").err(),
+            TraceFormat::JsonLines => None,
+        };
+        TraceWriter {
+            writer,
+            format,
+            error,
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Tracer for TraceWriter<W> {
+    fn record(&mut self, step: &TraceStep) {
+        if self.error.is_some() {
+            return;
+        }
+        let result = match self.format {
+            TraceFormat::JsonLines => writeln!(
+                self.writer,
+                "{{\"step\":{},\"pc\":{},\"op\":\"{}\",\"arg\":{},\"acc_before\":{},\"acc_after\":{}}}",
+                step.step,
+                step.pc,
+                step.op,
+                step.value,
+                step.accumulator_before,
+                step.accumulator_after
+            ),
+            TraceFormat::Csv => writeln!(
+                self.writer,
+                "{},{},{},{},{},{}",
+                step.step,
+                step.pc,
+                step.op,
+                step.value,
+                step.accumulator_before,
+                step.accumulator_after
+            ),
+        };
+        self.error = result.err();
+    }
+}
+
+// hit count per instruction, can be fed several runs of the same program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    hits: Vec<u64>,
+}
+
+impl Profile {
+    pub fn new(program_len: usize) -> Self {
+        Profile {
+            hits: vec![0; program_len],
+        }
+    }
+
+    pub fn hits(&self) -> &[u64] {
+        &self.hits
+    }
+
+    pub fn executed(&self) -> usize {
+        self.hits.iter().filter(|&&hits| hits > 0).count()
+    }
+
+    // fraction of instructions executed at least once, an empty program counts as covered
+    pub fn coverage(&self) -> f64 {
+        if self.hits.is_empty() {
+            1.0
+        } else {
+            self.executed() as f64 / self.hits.len() as f64
+        }
+    }
+
+    // instructions that never ran
+    pub fn dead_code(&self) -> Vec<usize> {
+        (0..self.hits.len())
+            .filter(|&pc| self.hits[pc] == 0)
+            .collect()
+    }
+
+    // the n most executed instructions as (pc, hits), ties broken by pc
+    pub fn hot_spots(&self, n: usize) -> Vec<(usize, u64)> {
+        let mut spots = self
+            .hits
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|&(_, hits)| hits > 0)
+            .collect::<Vec<(usize, u64)>>();
+        spots.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        spots.truncate(n);
+        spots
+    }
+
+    pub fn write_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "pc,hits")?;
+        for (pc, hits) in self.hits.iter().enumerate() {
+            writeln!(writer, "{},{}", pc, hits)?;
+        }
+        Ok(())
+    }
+}
+
+impl Tracer for Profile {
+    fn record(&mut self, step: &TraceStep) {
+        if step.pc >= self.hits.len() {
+            self.hits.resize(step.pc + 1, 0);
+        }
+        self.hits[step.pc] += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_support::SAMPLE;
+    use super::super::{Program, ProgramState};
+    use super::*;
+
+    #[test]
+    fn trace_formats() {
+        let program = Program::parse("acc +2\njmp +1\nacc -1").unwrap();
+        let mut tracer = TraceWriter::new(Vec::<u8>::new(), TraceFormat::Csv);
+        program.run_traced(&ProgramState::new(), None, &mut tracer);
+        let csv = String::from_utf8(tracer.finish().unwrap()).unwrap();
+        assert_eq!(
+            csv,
+            "step,pc,op,arg,acc_before,acc_after\n0,0,acc,2,0,2\n1,1,jmp,1,2,2\n2,2,acc,-1,2,1\n"
+        );
+
+        let mut tracer = TraceWriter::new(Vec::<u8>::new(), TraceFormat::JsonLines);
+        program.run_traced(&ProgramState::new(), Some(1), &mut tracer);
+        let json = String::from_utf8(tracer.finish().unwrap()).unwrap();
+        assert_eq!(
+            json,
+            "{\"step\":0,\"pc\":0,\"op\":\"acc\",\"arg\":2,\"acc_before\":0,\"acc_after\":2}\n"
+        );
+    }
+
+    #[test]
+    fn profile() {
+        let program = Program::parse(SAMPLE).unwrap();
+        let mut steps = Vec::<TraceStep>::new();
+        let mut profile = Profile::new(program.len());
+        program.run_traced(&ProgramState::new(), None, &mut (&mut steps, &mut profile));
+        assert_eq!(steps.len(), 7);
+        assert_eq!(profile.dead_code(), vec![5, 8]);
+        assert_eq!(profile.executed(), 7);
+
+        // a second, fixed run over the same profile
+        let fixed = Program::parse(&SAMPLE.replace("jmp -4", "nop -4")).unwrap();
+        fixed.run_traced(&ProgramState::new(), None, &mut profile);
+        assert_eq!(profile.dead_code(), vec![5]);
+        assert_eq!(profile.hot_spots(2), vec![(0, 2), (1, 2)]);
+    }
+}
@@ -1,67 +1,176 @@
-#[aoc_generator(day1)]
-pub fn input_generator(input: &str) -> Vec<u32> {
+pub mod closest;
+pub mod index;
+pub mod ksum;
+pub mod subsets;
+
+use closest::Bound;
+use std::env;
+use std::fmt;
+
+// entries are signed and 64 bit. sums are compared in i128 so they can't overflow, and products
+// are checked, coming back as an Overflow error rather than wrapping
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    // 1-based
+    pub line: usize,
+    pub text: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid entry at line {}: {:?}", self.line, self.text)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Overflow {
+    pub values: Vec<i64>,
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let values = self
+            .values
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<String>>();
+        write!(f, "{} overflows an i64", values.join(" * "))
+    }
+}
+
+impl std::error::Error for Overflow {}
+
+pub fn product(values: &[i64]) -> Result<i64, Overflow> {
+    values
+        .iter()
+        .try_fold(1i64, |product, &value| product.checked_mul(value))
+        .ok_or_else(|| Overflow {
+            values: values.to_vec(),
+        })
+}
+
+// one entry per line, blank lines are skipped and anything else that isn't an integer is an error
+pub fn parse(input: &str) -> Result<Vec<i64>, ParseError> {
     input
         .lines()
-        .map(|l| {
-            let s = String::from(l.trim());
-            if let Ok(n) = s.parse::<u32>() {
-                n
-            } else {
-                0
-            }
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(line, text)| {
+            text.parse::<i64>().map_err(|_| ParseError {
+                line,
+                text: String::from(text),
+            })
         })
         .collect()
 }
 
-#[aoc(day1, part1, loop)]
-pub fn part1(input: &Vec<u32>) -> u32 {
-    for a in input {
-        for b in input {
-            if a + b == 2020 {
-                return a * b;
+#[aoc_generator(day1)]
+pub fn input_generator(input: &str) -> Vec<i64> {
+    parse(input).unwrap_or_else(|e| panic!("{}", e))
+}
+
+// what the runner prints, which has no way to show a missing answer
+fn answer(input: &[i64], result: Result<Option<i64>, Overflow>, k: usize) -> i64 {
+    match result {
+        Ok(Some(product)) => product,
+        Ok(None) => match closest::closest(input, 2020, k, Bound::Nearest) {
+            Some(nearest) => panic!(
+                "no {} entries add up to 2020, the closest are {:?} making {}",
+                k, nearest.found.values, nearest.sum
+            ),
+            None => panic!("there are fewer than {} entries", k),
+        },
+        Err(e) => panic!("{}", e),
+    }
+}
+
+pub fn find_pair(input: &[i64], target: i64) -> Result<Option<i64>, Overflow> {
+    for (i, &a) in input.iter().enumerate() {
+        for &b in &input[i + 1..] {
+            if a as i128 + b as i128 == target as i128 {
+                return product(&[a, b]).map(Some);
             }
         }
     }
-    0
+    Ok(None)
 }
 
-#[aoc(day1, part2, loop)]
-pub fn part2(input: &Vec<u32>) -> u32 {
-    for a in input {
-        for b in input {
-            for c in input {
-                if a + b + c == 2020 {
-                    return a * b * c;
+pub fn find_triple(input: &[i64], target: i64) -> Result<Option<i64>, Overflow> {
+    for (i, &a) in input.iter().enumerate() {
+        for (j, &b) in input.iter().enumerate().skip(i + 1) {
+            for &c in &input[j + 1..] {
+                if a as i128 + b as i128 + c as i128 == target as i128 {
+                    return product(&[a, b, c]).map(Some);
                 }
             }
         }
     }
-    0
+    Ok(None)
+}
+
+#[aoc(day1, part1, loop)]
+pub fn part1(input: &[i64]) -> i64 {
+    answer(input, find_pair(input, 2020), 2)
+}
+
+#[aoc(day1, part2, loop)]
+pub fn part2(input: &[i64]) -> i64 {
+    answer(input, find_triple(input, 2020), 3)
 }
 
 use itertools::Itertools;
 
-fn find_match(input: &Vec<u32>, sum_match: u32, num: usize) -> u32 {
-    let mut result = 0;
-    for pair in input.iter().combinations(num) {
-        let literal = pair.iter().map(|x| **x as u32);
-        let sum: u32 = literal.clone().sum();
-        if sum == sum_match {
-            result = literal.clone().product();
-            break;
+pub fn find_match(input: &[i64], sum_match: i64, num: usize) -> Result<Option<i64>, Overflow> {
+    for combination in input.iter().copied().combinations(num) {
+        let sum: i128 = combination.iter().map(|&x| x as i128).sum();
+        if sum == sum_match as i128 {
+            return product(&combination).map(Some);
         }
     }
-    result
+    Ok(None)
 }
 
 #[aoc(day1, part1, iter)]
-pub fn part1_iter(input: &Vec<u32>) -> u32 {
-    find_match(input, 2020, 2)
+pub fn part1_iter(input: &[i64]) -> i64 {
+    answer(input, find_match(input, 2020, 2), 2)
 }
 
 #[aoc(day1, part2, iter)]
-pub fn part2_iter(input: &Vec<u32>) -> u32 {
-    find_match(input, 2020, 3)
+pub fn part2_iter(input: &[i64]) -> i64 {
+    answer(input, find_match(input, 2020, 3), 3)
+}
+
+// product of the first k entries found adding up to target, None if there are none
+pub fn solve(input: &[i64], target: i64, k: usize) -> Result<Option<i64>, Overflow> {
+    match ksum::find(input, target, k) {
+        Some(found) => found.product().map(Some),
+        None => Ok(None),
+    }
+}
+
+// the runner can't pass arguments, so the ksum variants take k from an environment variable when
+// it's set, DAY1_K1 for part 1 and DAY1_K2 for part 2, e.g. `DAY1_K2=4 cargo run --release`
+fn k_or(variable: &str, default: usize) -> usize {
+    env::var(variable)
+        .ok()
+        .and_then(|k| k.parse().ok())
+        .unwrap_or(default)
+}
+
+#[aoc(day1, part1, ksum)]
+pub fn part1_ksum(input: &[i64]) -> i64 {
+    let k = k_or("DAY1_K1", 2);
+    answer(input, solve(input, 2020, k), k)
+}
+
+#[aoc(day1, part2, ksum)]
+pub fn part2_ksum(input: &[i64]) -> i64 {
+    let k = k_or("DAY1_K2", 3);
+    answer(input, solve(input, 2020, k), k)
 }
 
 #[cfg(test)]
@@ -78,4 +187,57 @@ mod tests {
                 1456";
         assert_eq!(part1(&input_generator(example)), 514579);
     }
+
+    const SAMPLE: &str = "1721\r\n
+                979\r\n
+                366\r\n
+                299\r\n
+                675\r\n
+                1456";
+
+    #[test]
+    fn sample2() {
+        // blank lines used to read as 0, giving 1721 + 0 + 299
+        let input = input_generator(SAMPLE);
+        assert_eq!(part2(&input), 241861950);
+        assert_eq!(part2_iter(&input), 241861950);
+        assert_eq!(solve(&input, 2020, 3), Ok(Some(241861950)));
+        assert_eq!(solve(&input, 2020, 2), Ok(Some(514579)));
+    }
+
+    #[test]
+    fn no_self_pairs() {
+        let input = vec![1010, 3];
+        assert_eq!(find_pair(&input, 2020), Ok(None));
+        assert_eq!(find_match(&input, 2020, 2), Ok(None));
+        assert_eq!(solve(&input, 2020, 2), Ok(None));
+        assert_eq!(part1(&[1010, 3, 1010]), 1010 * 1010);
+    }
+
+    #[test]
+    fn strict() {
+        assert_eq!(parse("5\n\n-7\n+9\n"), Ok(vec![5, -7, 9]));
+        assert_eq!(
+            parse("5\n12x\n3").unwrap_err(),
+            ParseError {
+                line: 2,
+                text: String::from("12x")
+            }
+        );
+        assert_eq!(
+            parse("99999999999999999999").unwrap_err().to_string(),
+            "invalid entry at line 1: \"99999999999999999999\""
+        );
+
+        assert_eq!(find_pair(&[-5, 7, 2025], 2020), Ok(Some(-10125)));
+        // 3037000500 squared is just past i64::MAX
+        let big = [3_037_000_500, 3_037_000_500, 2020 - 6_074_001_000];
+        let error = solve(&big, 2020, 3).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "3037000500 * 3037000500 * -6073998980 overflows an i64"
+        );
+        assert_eq!(find_triple(&big, 2020), Err(error.clone()));
+        assert_eq!(find_match(&big, 2020, 3), Err(error));
+    }
 }
@@ -0,0 +1,176 @@
+use super::ksum::{sorted_with_indices, KSum};
+
+// the k entries whose sum is nearest to a target when none hit it exactly, for budgeting. same
+// shape as the k >= 3 search in ksum, sorting once and fixing entries down to a two pointer scan,
+// only it keeps the best sum seen instead of stopping at the first match. O(n^(k-1))
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    // either side of the target, ties go to the smaller sum
+    Nearest,
+    // never over the target
+    AtMost,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Closest {
+    pub found: KSum,
+    pub sum: i128,
+    // how far sum is from the target, 0 for an exact match
+    pub distance: u128,
+}
+
+struct Search<'a> {
+    // (value, index) by value
+    sorted: &'a [(i64, usize)],
+    target: i128,
+    bound: Bound,
+    // positions in sorted
+    chosen: Vec<usize>,
+    best: Option<(i128, Vec<usize>)>,
+}
+
+impl<'a> Search<'a> {
+    fn value(&self, position: usize) -> i128 {
+        self.sorted[position].0 as i128
+    }
+
+    fn offer(&mut self, sum: i128, rest: &[usize]) {
+        if self.bound == Bound::AtMost && sum > self.target {
+            return;
+        }
+        let distance = |sum: i128| (sum - self.target).unsigned_abs();
+        let better = match &self.best {
+            Some((best, _)) => {
+                distance(sum) < distance(*best) || (distance(sum) == distance(*best) && sum < *best)
+            }
+            None => true,
+        };
+        if better {
+            let positions = self.chosen.iter().chain(rest).copied().collect();
+            self.best = Some((sum, positions));
+        }
+    }
+
+    // k more entries from position from on, with sum so far. true once there's an exact match,
+    // which nothing can beat
+    fn search(&mut self, from: usize, k: usize, sum: i128) -> bool {
+        let n = self.sorted.len();
+        match k {
+            0 => self.offer(sum, &[]),
+            1 => {
+                for position in from..n {
+                    self.offer(sum + self.value(position), &[position]);
+                }
+            }
+            2 => {
+                let (mut lo, mut hi) = (from, n - 1);
+                while lo < hi {
+                    let pair = sum + self.value(lo) + self.value(hi);
+                    self.offer(pair, &[lo, hi]);
+                    if pair == self.target {
+                        break;
+                    }
+                    if pair < self.target {
+                        lo += 1;
+                    } else {
+                        hi -= 1;
+                    }
+                }
+            }
+            _ => {
+                for i in from..=n - k {
+                    if i > from && self.value(i - 1) == self.value(i) {
+                        continue;
+                    }
+                    // the smallest sum from here on is already too big, so it's the best of them
+                    let smallest = sum + (i..i + k).map(|p| self.value(p)).sum::<i128>();
+                    if smallest > self.target {
+                        self.offer(smallest, &(i..i + k).collect::<Vec<usize>>());
+                        break;
+                    }
+                    self.chosen.push(i);
+                    let exact = self.search(i + 1, k - 1, sum + self.value(i));
+                    self.chosen.pop();
+                    if exact {
+                        return true;
+                    }
+                }
+            }
+        }
+        self.best
+            .as_ref()
+            .is_some_and(|(best, _)| *best == self.target)
+    }
+}
+
+// None if there are fewer than k entries, or for Bound::AtMost when every choice is over
+pub fn closest(entries: &[i64], target: i64, k: usize, bound: Bound) -> Option<Closest> {
+    if k > entries.len() {
+        return None;
+    }
+    let sorted = sorted_with_indices(entries);
+    let mut search = Search {
+        sorted: &sorted,
+        target: target as i128,
+        bound,
+        chosen: Vec::with_capacity(k),
+        best: None,
+    };
+    search.search(0, k, 0);
+    let (sum, positions) = search.best?;
+    let indices = positions.iter().map(|&p| sorted[p].1).collect();
+    Some(Closest {
+        found: KSum::new(entries, indices),
+        sum,
+        distance: (sum - target as i128).unsigned_abs(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itertools::Itertools;
+    use proptest::prelude::*;
+
+    #[test]
+    fn budget() {
+        let entries = [1721, 979, 366, 299, 675, 1456];
+        let over = closest(&entries, 2000, 2, Bound::Nearest).unwrap();
+        assert_eq!((over.sum, over.distance), (2020, 20));
+        assert_eq!(over.found.values, vec![1721, 299]);
+        let under = closest(&entries, 2000, 2, Bound::AtMost).unwrap();
+        assert_eq!((under.sum, under.distance), (1822, 178));
+        assert_eq!(under.found.indices, vec![2, 5]);
+
+        assert_eq!(
+            closest(&entries, 2020, 3, Bound::Nearest).unwrap().distance,
+            0
+        );
+        assert_eq!(closest(&entries, 500, 2, Bound::AtMost), None);
+        assert_eq!(closest(&entries, 500, 7, Bound::Nearest), None);
+    }
+
+    proptest! {
+        #[test]
+        fn matches_brute_force(
+            entries in prop::collection::vec(-30i64..30, 0..10),
+            target in -60i64..60,
+            k in 0usize..5,
+            at_most: bool,
+        ) {
+            let bound = if at_most { Bound::AtMost } else { Bound::Nearest };
+            let best = (0..entries.len())
+                .combinations(k)
+                .map(|c| c.iter().map(|&i| entries[i] as i128).sum::<i128>())
+                .filter(|&sum| !at_most || sum <= target as i128)
+                .min_by_key(|&sum| ((sum - target as i128).abs(), sum));
+            let found = closest(&entries, target, k, bound);
+            prop_assert_eq!(found.as_ref().map(|c| c.sum), best);
+            if let Some(found) = found {
+                prop_assert_eq!(found.found.values.iter().map(|&v| v as i128).sum::<i128>(), found.sum);
+                prop_assert_eq!(found.found.indices.len(), k);
+            }
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use super::ksum::KSum;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+// for running many (target, k) queries over one expense list. building it hashes every pair sum
+// once, O(n^2), after which a pair is one lookup, and k entries fix k - 2 of them in increasing
+// index order and look the last pair up, so O(n^(k-2)) per query against ksum's O(n^(k-1)).
+//
+// for each sum only the pair with the largest first index is kept. the search needs a pair after
+// everything it has fixed, and if that one isn't, no pair with the same sum is
+
+pub struct PairIndex {
+    entries: Vec<i64>,
+    // sum -> (i, j), i < j, with i as large as it gets for that sum
+    pairs: HashMap<i128, (usize, usize)>,
+    // value -> its last index
+    last: HashMap<i64, usize>,
+}
+
+impl PairIndex {
+    pub fn new(entries: &[i64]) -> Self {
+        let mut pairs = HashMap::with_capacity(entries.len() * entries.len() / 2);
+        let mut last = HashMap::with_capacity(entries.len());
+        for (i, &a) in entries.iter().enumerate() {
+            for (j, &b) in entries.iter().enumerate().skip(i + 1) {
+                // later i overwrite earlier ones
+                pairs.insert(a as i128 + b as i128, (i, j));
+            }
+            last.insert(a, i);
+        }
+        PairIndex {
+            entries: entries.to_vec(),
+            pairs,
+            last,
+        }
+    }
+
+    pub fn entries(&self) -> &[i64] {
+        &self.entries
+    }
+
+    // indices from on, k of them, adding up to target
+    fn search(&self, from: usize, k: usize, target: i128, chosen: &mut Vec<usize>) -> bool {
+        match k {
+            0 => target == 0,
+            1 => match i64::try_from(target).ok().and_then(|t| self.last.get(&t)) {
+                Some(&i) if i >= from => {
+                    chosen.push(i);
+                    true
+                }
+                _ => false,
+            },
+            2 => match self.pairs.get(&target) {
+                Some(&(i, j)) if i >= from => {
+                    chosen.extend([i, j]);
+                    true
+                }
+                _ => false,
+            },
+            _ => {
+                for i in from..self.entries.len() {
+                    chosen.push(i);
+                    if self.search(i + 1, k - 1, target - self.entries[i] as i128, chosen) {
+                        return true;
+                    }
+                    chosen.pop();
+                }
+                false
+            }
+        }
+    }
+
+    // some k entries at distinct indices adding up to target, like ksum::find
+    pub fn query(&self, target: i64, k: usize) -> Option<KSum> {
+        let mut chosen = Vec::with_capacity(k);
+        if self.search(0, k, target as i128, &mut chosen) {
+            Some(KSum::new(&self.entries, chosen))
+        } else {
+            None
+        }
+    }
+
+    pub fn query_all(&self, queries: &[(i64, usize)]) -> Vec<Option<KSum>> {
+        queries
+            .iter()
+            .map(|&(target, k)| self.query(target, k))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ksum;
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn sample() {
+        let index = PairIndex::new(&[1721, 979, 366, 299, 675, 1456]);
+        let answers = index.query_all(&[(2020, 2), (2020, 3), (1345, 2), (2020, 4), (366, 1)]);
+        let values = answers
+            .iter()
+            .map(|found| found.as_ref().map(|found| found.values.clone()))
+            .collect::<Vec<Option<Vec<i64>>>>();
+        assert_eq!(
+            values,
+            vec![
+                Some(vec![1721, 299]),
+                Some(vec![979, 366, 675]),
+                Some(vec![979, 366]),
+                None,
+                Some(vec![366]),
+            ]
+        );
+        // no pairing an entry with itself
+        assert_eq!(PairIndex::new(&[1010, 3]).query(2020, 2), None);
+    }
+
+    proptest! {
+        #[test]
+        fn matches_ksum(
+            entries in prop::collection::vec(-20i64..20, 0..12),
+            queries in prop::collection::vec((-40i64..40, 0usize..6), 1..10),
+        ) {
+            let index = PairIndex::new(&entries);
+            for (&(target, k), found) in queries.iter().zip(index.query_all(&queries)) {
+                match found {
+                    Some(found) => {
+                        prop_assert_eq!(found.indices.len(), k);
+                        prop_assert!(found.indices.windows(2).all(|w| w[0] < w[1]));
+                        prop_assert_eq!(found.values.iter().sum::<i64>(), target);
+                    }
+                    None => prop_assert_eq!(ksum::find(&entries, target, k), None),
+                }
+            }
+        }
+    }
+}
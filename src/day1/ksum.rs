@@ -0,0 +1,170 @@
+use super::{product, Overflow};
+use std::collections::HashMap;
+
+// k entries at distinct indices adding up to a target. k = 2 is a single pass with a hash map,
+// anything larger sorts once and fixes entries one at a time down to a two pointer scan over the
+// rest, so O(n) for pairs and O(n^(k-1)) beyond. sums are worked out in i128 so no choice of
+// entries can overflow them
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KSum {
+    // ascending, values[i] is the entry at indices[i]
+    pub indices: Vec<usize>,
+    pub values: Vec<i64>,
+}
+
+impl KSum {
+    // the entries at indices, in any order
+    pub fn new(entries: &[i64], mut indices: Vec<usize>) -> Self {
+        indices.sort_unstable();
+        let values = indices.iter().map(|&i| entries[i]).collect();
+        KSum { indices, values }
+    }
+
+    pub fn product(&self) -> Result<i64, Overflow> {
+        product(&self.values)
+    }
+}
+
+pub fn find(entries: &[i64], target: i64, k: usize) -> Option<KSum> {
+    let indices = match k {
+        0 => Some(Vec::new()).filter(|_| target == 0),
+        1 => entries.iter().position(|&a| a == target).map(|i| vec![i]),
+        2 => pair(entries, target),
+        _ => {
+            let sorted = sorted_with_indices(entries);
+            let mut chosen = Vec::with_capacity(k);
+            if sorted_ksum(&sorted, target as i128, k, &mut chosen) {
+                Some(chosen)
+            } else {
+                None
+            }
+        }
+    }?;
+    Some(KSum::new(entries, indices))
+}
+
+// (value, index) for every entry, by value
+pub(super) fn sorted_with_indices(entries: &[i64]) -> Vec<(i64, usize)> {
+    let mut sorted = entries
+        .iter()
+        .copied()
+        .enumerate()
+        .map(|(i, value)| (value, i))
+        .collect::<Vec<(i64, usize)>>();
+    sorted.sort_unstable();
+    sorted
+}
+
+fn pair(entries: &[i64], target: i64) -> Option<Vec<usize>> {
+    // first index of each value seen so far
+    let mut seen = HashMap::<i64, usize>::with_capacity(entries.len());
+    for (j, &b) in entries.iter().enumerate() {
+        if let Some(&i) = target.checked_sub(b).and_then(|a| seen.get(&a)) {
+            return Some(vec![i, j]);
+        }
+        seen.entry(b).or_insert(j);
+    }
+    None
+}
+
+// sorted holds (value, index) pairs by value, k >= 2
+fn sorted_ksum(sorted: &[(i64, usize)], target: i128, k: usize, chosen: &mut Vec<usize>) -> bool {
+    if sorted.len() < k {
+        return false;
+    }
+    if k == 2 {
+        let (mut lo, mut hi) = (0, sorted.len() - 1);
+        while lo < hi {
+            let sum = sorted[lo].0 as i128 + sorted[hi].0 as i128;
+            if sum == target {
+                chosen.extend([sorted[lo].1, sorted[hi].1]);
+                return true;
+            }
+            if sum < target {
+                lo += 1;
+            } else {
+                hi -= 1;
+            }
+        }
+        return false;
+    }
+
+    let sum = |entries: &[(i64, usize)]| entries.iter().map(|&(a, _)| a as i128).sum::<i128>();
+    let largest_rest = sum(&sorted[sorted.len() - (k - 1)..]);
+    for i in 0..=sorted.len() - k {
+        let a = sorted[i].0;
+        // equal values give the same sums as the first of them did
+        if i > 0 && sorted[i - 1].0 == a {
+            continue;
+        }
+        // everything after here only gets bigger
+        if a as i128 + sum(&sorted[i + 1..i + k]) > target {
+            break;
+        }
+        if a as i128 + largest_rest < target {
+            continue;
+        }
+        chosen.push(sorted[i].1);
+        if sorted_ksum(&sorted[i + 1..], target - a as i128, k - 1, chosen) {
+            return true;
+        }
+        chosen.pop();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itertools::Itertools;
+    use proptest::prelude::*;
+
+    #[test]
+    fn distinct_indices() {
+        // 1010 + 1010 needs both copies, one isn't enough
+        assert_eq!(find(&[1010, 5], 2020, 2), None);
+        let found = find(&[7, 1010, 5, 1010], 2020, 2).unwrap();
+        assert_eq!(found.indices, vec![1, 3]);
+        assert_eq!(found.values, vec![1010, 1010]);
+
+        let found = find(&[3, -4, 10, 1, 1], 8, 4).unwrap();
+        assert_eq!(
+            found
+                .indices
+                .iter()
+                .map(|&i| [3, -4, 10, 1, 1][i])
+                .sum::<i64>(),
+            8
+        );
+        assert_eq!(find(&[1, 2], 3, 3), None);
+        assert_eq!(find(&[1, 2], 0, 0).unwrap().indices, Vec::<usize>::new());
+        assert_eq!(
+            find(&[i64::MAX, i64::MAX, -2], i64::MAX - 2, 2)
+                .unwrap()
+                .indices,
+            vec![0, 2]
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn matches_brute_force(
+            entries in prop::collection::vec(-20i64..20, 0..12),
+            target in -40i64..40,
+            k in 0usize..5,
+        ) {
+            let expected = (0..entries.len())
+                .combinations(k)
+                .any(|c| c.iter().map(|&i| entries[i]).sum::<i64>() == target);
+            match find(&entries, target, k) {
+                Some(found) => {
+                    prop_assert_eq!(found.indices.len(), k);
+                    prop_assert!(found.indices.windows(2).all(|w| w[0] < w[1]));
+                    prop_assert_eq!(found.values.iter().sum::<i64>(), target);
+                }
+                None => prop_assert!(!expected),
+            }
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use super::ksum::{sorted_with_indices, KSum};
+use std::collections::{BTreeMap, HashMap};
+
+// every k entries adding up to a target, rather than just the first (see ksum). there are two
+// ways to treat equal values: count and solutions take each entry as its own expense, so with
+// [5, 5, 5] there are 3 pairs making 10, while count_distinct and distinct_solutions only tell
+// apart different multisets of values, so there's just the one.
+//
+// counting is a dp over (entries picked, sum) that takes each distinct value once with all of its
+// copies, so it never walks the subsets themselves. counts are u128 with checked arithmetic, and
+// None means the exact count didn't fit
+
+fn gcd(a: u128, b: u128) -> u128 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// m choose c, None if it doesn't fit
+fn binomial(m: usize, c: usize) -> Option<u128> {
+    let mut result = 1u128;
+    for i in 1..=c as u128 {
+        // result * (m - i + 1) / i, divided out first so only the result itself can overflow
+        let g = gcd(result, i);
+        result = (result / g).checked_mul((m as u128 - i + 1) / (i / g))?;
+    }
+    Some(result)
+}
+
+// ways[j][sum] is how many ways j entries make sum, None once it has overflowed, which only
+// spreads to the cells built from it. weight(m, c) is how many ways there are to take c copies of
+// a value that appears m times
+fn count_with(
+    entries: &[i64],
+    target: i64,
+    k: usize,
+    weight: impl Fn(usize, usize) -> Option<u128>,
+) -> Option<u128> {
+    let mut copies = BTreeMap::<i64, usize>::new();
+    for &value in entries {
+        *copies.entry(value).or_insert(0) += 1;
+    }
+    let copies = copies.into_iter().collect::<Vec<(i64, usize)>>();
+    let target = target as i128;
+
+    let mut ways = vec![HashMap::<i128, Option<u128>>::new(); k + 1];
+    ways[0].insert(0, Some(1));
+    for (position, &(value, m)) in copies.iter().enumerate() {
+        // downwards, so ways[j - c] is still from before this value
+        for j in (1..=k).rev() {
+            let mut added = Vec::<(i128, Option<u128>)>::new();
+            for c in 1..=m.min(j) {
+                let w = weight(m, c);
+                for (&sum, &n) in &ways[j - c] {
+                    let n = n.zip(w).and_then(|(n, w)| n.checked_mul(w));
+                    added.push((sum + c as i128 * value as i128, n));
+                }
+            }
+            for (sum, n) in added {
+                let total = ways[j].entry(sum).or_insert(Some(0));
+                *total = total.zip(n).and_then(|(total, n)| total.checked_add(n));
+            }
+        }
+
+        // values still to come are bigger than this one, so drop the sums they can't take to the
+        // target with the entries left to pick
+        let rest = &copies[position + 1..];
+        let left = rest.iter().map(|&(_, m)| m).sum::<usize>();
+        let lowest = rest.first().map_or(0, |&(value, _)| value as i128);
+        let highest = rest.last().map_or(0, |&(value, _)| value as i128);
+        for (j, cells) in ways.iter_mut().enumerate() {
+            let picks = k - j;
+            if picks > left {
+                cells.clear();
+            } else {
+                let picks = picks as i128;
+                cells.retain(|&sum, _| {
+                    sum + picks * lowest <= target && target <= sum + picks * highest
+                });
+            }
+        }
+    }
+    ways[k].get(&target).copied().unwrap_or(Some(0))
+}
+
+// how many sets of k entries add up to target
+pub fn count(entries: &[i64], target: i64, k: usize) -> Option<u128> {
+    count_with(entries, target, k, binomial)
+}
+
+// how many different multisets of k values from entries add up to target
+pub fn count_distinct(entries: &[i64], target: i64, k: usize) -> Option<u128> {
+    count_with(entries, target, k, |_, _| Some(1))
+}
+
+// the solutions one at a time, in order of their values. the entries are sorted once, then it's
+// a depth first search that drops any branch whose smallest or largest possible sum misses target
+pub struct Solutions<'a> {
+    entries: &'a [i64],
+    // (value, index) by value
+    sorted: Vec<(i64, usize)>,
+    target: i128,
+    k: usize,
+    distinct: bool,
+    // positions in sorted picked so far, and their sum
+    picked: Vec<usize>,
+    sum: i128,
+    // position to try next at the current depth
+    next: usize,
+    done: bool,
+}
+
+impl<'a> Solutions<'a> {
+    fn new(entries: &'a [i64], target: i64, k: usize, distinct: bool) -> Self {
+        let sorted = sorted_with_indices(entries);
+        Solutions {
+            entries,
+            sorted,
+            target: target as i128,
+            k,
+            distinct,
+            picked: Vec::with_capacity(k),
+            sum: 0,
+            next: 0,
+            done: k > entries.len(),
+        }
+    }
+
+    // back up one level, which ends the search at the top
+    fn backtrack(&mut self) {
+        match self.picked.pop() {
+            Some(position) => {
+                self.sum -= self.sorted[position].0 as i128;
+                self.next = position + 1;
+            }
+            None => self.done = true,
+        }
+    }
+
+    fn solution(&self) -> KSum {
+        let indices = self
+            .picked
+            .iter()
+            .map(|&position| self.sorted[position].1)
+            .collect();
+        KSum::new(self.entries, indices)
+    }
+}
+
+impl<'a> Iterator for Solutions<'a> {
+    type Item = KSum;
+
+    fn next(&mut self) -> Option<KSum> {
+        let n = self.sorted.len();
+        while !self.done {
+            if self.picked.len() == self.k {
+                let found = (self.sum == self.target).then(|| self.solution());
+                self.backtrack();
+                if found.is_some() {
+                    return found;
+                }
+                continue;
+            }
+
+            let remaining = self.k - self.picked.len();
+            let i = self.next;
+            let first = self.picked.last().map_or(0, |&p| p + 1);
+            let sorted = &self.sorted;
+            let value = |position: usize| sorted[position].0 as i128;
+            // the smallest sum from here only grows with i
+            if i + remaining > n
+                || self.sum + (i..i + remaining).map(value).sum::<i128>() > self.target
+            {
+                self.backtrack();
+                continue;
+            }
+            let largest = self.sum + value(i) + (n + 1 - remaining..n).map(value).sum::<i128>();
+            if largest < self.target || (self.distinct && i > first && value(i - 1) == value(i)) {
+                self.next = i + 1;
+                continue;
+            }
+            self.sum += value(i);
+            self.picked.push(i);
+            self.next = i + 1;
+        }
+        None
+    }
+}
+
+pub fn solutions(entries: &[i64], target: i64, k: usize) -> Solutions<'_> {
+    Solutions::new(entries, target, k, false)
+}
+
+// one solution per multiset of values, the one using the earliest entries of each value
+pub fn distinct_solutions(entries: &[i64], target: i64, k: usize) -> Solutions<'_> {
+    Solutions::new(entries, target, k, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itertools::Itertools;
+    use proptest::prelude::*;
+
+    #[test]
+    fn duplicates() {
+        let entries = [5, 5, 5, 3, 7];
+        assert_eq!(count(&entries, 10, 2), Some(4));
+        assert_eq!(count_distinct(&entries, 10, 2), Some(2));
+        let pairs = solutions(&entries, 10, 2)
+            .map(|found| found.indices)
+            .collect::<Vec<Vec<usize>>>();
+        assert_eq!(pairs, vec![vec![3, 4], vec![0, 1], vec![0, 2], vec![1, 2]]);
+        let distinct = distinct_solutions(&entries, 10, 2)
+            .map(|found| found.values)
+            .collect::<Vec<Vec<i64>>>();
+        assert_eq!(distinct, vec![vec![3, 7], vec![5, 5]]);
+
+        assert_eq!(count(&entries, 0, 0), Some(1));
+        assert_eq!(solutions(&entries, 0, 0).count(), 1);
+        assert_eq!(solutions(&entries, 0, 6).count(), 0);
+    }
+
+    #[test]
+    fn large_counts() {
+        // 200 choose 30 still fits, 200 choose 100 doesn't
+        let zeros = vec![0; 200];
+        assert_eq!(
+            count(&zeros, 0, 30),
+            Some(409_681_705_022_127_773_530_866_523_638_950_880)
+        );
+        assert_eq!(count(&zeros, 0, 100), None);
+        assert_eq!(count_distinct(&zeros, 0, 100), Some(1));
+        // sums that overflow on the way don't matter when they can't make the target: the only
+        // way to 100 is every one of the ones
+        let mut entries = zeros.clone();
+        entries.extend(vec![1; 100]);
+        assert_eq!(count(&entries, 100, 100), Some(1));
+        // and the first solution comes out without looking at the rest
+        assert_eq!(solutions(&zeros, 0, 100).next().unwrap().indices.len(), 100);
+    }
+
+    proptest! {
+        #[test]
+        fn matches_brute_force(
+            entries in prop::collection::vec(-6i64..6, 0..10),
+            target in -15i64..15,
+            k in 0usize..5,
+        ) {
+            let brute = (0..entries.len())
+                .combinations(k)
+                .filter(|c| c.iter().map(|&i| entries[i]).sum::<i64>() == target)
+                .collect::<Vec<Vec<usize>>>();
+            let mut found = solutions(&entries, target, k)
+                .map(|found| found.indices)
+                .collect::<Vec<Vec<usize>>>();
+            found.sort();
+            prop_assert_eq!(&found, &brute);
+            prop_assert_eq!(count(&entries, target, k), Some(brute.len() as u128));
+
+            let mut multisets = brute
+                .iter()
+                .map(|c| c.iter().map(|&i| entries[i]).sorted().collect::<Vec<i64>>())
+                .collect::<Vec<Vec<i64>>>();
+            multisets.sort();
+            multisets.dedup();
+            let distinct = distinct_solutions(&entries, target, k)
+                .map(|found| found.values.into_iter().sorted().collect::<Vec<i64>>())
+                .collect::<Vec<Vec<i64>>>();
+            prop_assert_eq!(distinct, multisets.clone());
+            prop_assert_eq!(count_distinct(&entries, target, k), Some(multisets.len() as u128));
+        }
+    }
+}
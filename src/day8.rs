@@ -2,23 +2,23 @@ use crate::console::*;
 
 #[aoc_generator(day8)]
 pub fn input_generator(input: &str) -> Program {
-    Program::parse(input)
+    Program::parse(input).unwrap_or_else(|e| panic!("{}", e))
 }
 
 #[aoc(day8, part1)]
 pub fn part1(program: &Program) -> i32 {
     match program.run() {
-        Ok(_) => panic!("this should not return correctly"),
-        Err(state_after_single_loop) => return state_after_single_loop.accumulator,
-    };
+        Termination::InfiniteLoop { state, .. } => state.accumulator,
+        other => panic!("expected an infinite loop, got {:?}", other),
+    }
 }
 
 #[aoc(day8, part2)]
 pub fn part2(program: &Program) -> i32 {
     match program.fix_and_run() {
-        Ok(state_after_completion) => return state_after_completion.accumulator,
-        Err(_) => panic!("this should complete"),
-    };
+        Termination::Halted(state) => state.accumulator,
+        other => panic!("expected the fixed program to halt, got {:?}", other),
+    }
 }
 
 #[cfg(test)]
@@ -52,4 +52,20 @@ mod tests {
                         acc +6";
         assert_eq!(part2(&input_generator(sample)), 8);
     }
+
+    #[test]
+    fn generated() {
+        let mut generator = crate::console::generate::Generator::new(2020);
+        for &(len, answer) in &[(2, -5), (9, 8), (600, 1766), (5000, i32::MAX)] {
+            let (program, repair) = generator.looping(len, answer);
+            let input = program.to_string();
+            let looped_at = match program.run() {
+                Termination::InfiniteLoop { state, .. } => state.accumulator,
+                other => panic!("{:?}", other),
+            };
+            assert_eq!(part1(&input_generator(&input)), looped_at);
+            assert_eq!(part2(&input_generator(&input)), answer);
+            assert_eq!(repair.accumulator, answer);
+        }
+    }
 }
@@ -5,7 +5,7 @@ extern crate aoc_runner_derive;
 
 pub mod console;
 
-// pub mod day1;
+pub mod day1;
 // pub mod day10;
 // pub mod day11;
 // pub mod day12;
@@ -19,7 +19,7 @@ pub mod day16;
 // pub mod day5;
 // pub mod day6;
 // pub mod day7;
-// pub mod day8;
+pub mod day8;
 // pub mod day9;
 
 aoc_lib! { year = 2020 }